@@ -1,8 +1,17 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy_common_assets::ron::RonAssetPlugin;
 use bevy_kira_audio::AudioSource;
 use bevy_kira_audio::prelude::*;
+use rand::random;
+use serde::Deserialize;
 
-use crate::loading::AudioAssets;
+use crate::loading::{AudioAssets, Effects};
+use crate::settings::Settings;
+use crate::veggie::Veggie;
 
 pub struct InternalAudioPlugin;
 
@@ -10,11 +19,18 @@ impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_plugin(AudioPlugin)
-            .add_system(update_bgm)
+            .add_plugin(RonAssetPlugin::<SoundTable>::new(&["sounds.ron"]))
+            .add_system(apply_volumes)
+            .add_system(play_bgm.after(apply_volumes))
+            .add_system(play_sfx.after(apply_volumes))
+            .add_system(update_layers.after(play_bgm))
+            .init_resource::<Harmony>()
+            .init_resource::<Layers>()
             .add_event::<PlayBgmEvent>()
             .add_event::<PlaySfxEvent>()
             .add_audio_channel::<BgmChannel>()
-            .add_audio_channel::<SfxChannel>();
+            .add_audio_channel::<SfxChannel>()
+            .add_audio_channel::<LayerChannel>();
     }
 }
 
@@ -26,77 +42,264 @@ pub enum BGM {
 }
 
 impl BGM {
-    fn get_handle(&self, audio_assets: &Res<AudioAssets>) -> Handle<AudioSource> {
+    /// Tracks played one after the other, a single track loops
+    fn playlist(&self, audio_assets: &Res<AudioAssets>) -> Vec<Handle<AudioSource>> {
         match self {
-            BGM::Title => audio_assets.title.clone(),
-            BGM::Editor => audio_assets.editor.clone(),
-            BGM::Level => audio_assets.level.clone(),
+            BGM::Title => vec![audio_assets.title.clone()],
+            BGM::Editor => vec![audio_assets.editor.clone()],
+            BGM::Level => vec![audio_assets.level.last().unwrap().clone()],
         }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Deserialize)]
 pub enum SFX {
     Clic,
     Error,
     Place,
     Win,
+    PickUp(Veggie),
+    Plant(Veggie),
+    BecameSad,
+    BecameHappy,
 }
 
 impl SFX {
-    fn get_handle(&self, audio_assets: &Res<AudioAssets>) -> Handle<AudioSource> {
+    /// Sound played when the table has none for this one
+    fn fallback(&self) -> Option<SFX> {
         match self {
-            SFX::Clic => audio_assets.clic.clone(),
-            SFX::Error => audio_assets.error.clone(),
-            SFX::Place => audio_assets.place.clone(),
-            SFX::Win => audio_assets.win.clone(),
+            SFX::PickUp(_) => Some(SFX::Clic),
+            SFX::Plant(_) => Some(SFX::Place),
+            _ => None,
         }
     }
 }
 
+/// Sound effects of the game, read from `effects.sounds.ron`
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "5d3c9a0e-7f41-4b6a-a2c8-1e9f0b7d4c53"]
+pub struct SoundTable {
+    /// Sounds playing at once, the next ones are dropped
+    max_sounds: usize,
+    sounds: HashMap<SFX, Sound>,
+}
+
+impl SoundTable {
+    /// Every file the sounds pick from
+    pub fn files(&self) -> impl Iterator<Item=&String> {
+        self.sounds.values().flat_map(|sound| sound.files.iter())
+    }
+}
+
+#[derive(Deserialize)]
+struct Sound {
+    /// One of them is picked at random
+    files: Vec<String>,
+    #[serde(default = "one")]
+    volume: f64,
+    #[serde(default = "one")]
+    rate: f64,
+    /// Random variations around the volume and rate, to avoid repetition
+    #[serde(default)]
+    volume_variation: f64,
+    #[serde(default)]
+    rate_variation: f64,
+}
+
+fn one() -> f64 { 1. }
+
+/// Random value in `value ± variation`
+fn vary(value: f64, variation: f64) -> f64 {
+    value * (1. + variation * (2. * random::<f64>() - 1.))
+}
+
 #[derive(Resource)]
 pub struct BgmChannel;
 
 #[derive(Resource)]
 pub struct SfxChannel;
 
+/// Mixes of the main theme played along the full arrangement
+#[derive(Resource)]
+pub struct LayerChannel;
+
+/// Share of happy veggies in the garden, the main theme gets fuller with it
+#[derive(Resource, Default)]
+pub struct Harmony(pub f32);
+
+/// Mixes of the main theme following the harmony, with the volume they were last given.
+/// The last one is the full arrangement, played on the music channel.
+#[derive(Resource, Default)]
+struct Layers(Vec<(Handle<AudioInstance>, Option<f64>)>);
+
 pub struct PlayBgmEvent(pub BGM);
 
 pub struct PlaySfxEvent(pub SFX);
 
-#[derive(Resource)]
-struct CurrentBGM(BGM);
+/// Track of the playlist being played
+struct CurrentBGM {
+    bgm: BGM,
+    track: usize,
+    instance: Handle<AudioInstance>,
+}
+
+/// Music fades out while the next one fades in
+const CROSSFADE: Duration = Duration::from_millis(1500);
+
+/// Mixes follow the garden quickly, the full arrangement plays by the time it is solved
+const LAYER_FADE: Duration = Duration::from_millis(400);
+
+/// The music follows the settings as they change, sounds get their volume when played
+fn apply_volumes(
+    settings: Res<Settings>,
+    bgm_channel: Res<AudioChannel<BgmChannel>>,
+) {
+    if settings.is_changed() {
+        bgm_channel.set_volume(settings.bgm_volume());
+    }
+}
 
-fn update_bgm(
+fn play_bgm(
     mut bgm_events: EventReader<PlayBgmEvent>,
-    mut sfx_events: EventReader<PlaySfxEvent>,
     audio_assets: Option<Res<AudioAssets>>,
     bgm_channel: Res<AudioChannel<BgmChannel>>,
-    sfx_channel: Res<AudioChannel<SfxChannel>>,
-    mut commands: Commands,
-    current: Option<Res<CurrentBGM>>,
+    layer_channel: Res<AudioChannel<LayerChannel>>,
+    mut instances: ResMut<Assets<AudioInstance>>,
+    mut current: Local<Option<CurrentBGM>>,
+    mut layers: ResMut<Layers>,
+    settings: Res<Settings>,
 ) {
+    // Only the last music asked for in the frame is played
+    let requested = bgm_events.iter().last().map(|PlayBgmEvent(bgm)| *bgm);
     if audio_assets.is_none() { return; }
+    let audio_assets = audio_assets.unwrap();
+
+    let next = match (requested, current.as_ref()) {
+        (Some(bgm), Some(c)) if c.bgm == bgm => None,
+        (Some(bgm), _) => Some((bgm, 0)),
+        // The track ended, on to the next one of the playlist
+        (None, Some(c)) if matches!(bgm_channel.state(&c.instance), PlaybackState::Stopped) => Some((c.bgm, c.track + 1)),
+        _ => None,
+    };
+
+    if let Some((bgm, track)) = next {
+        if let Some(instance) = current.as_ref().and_then(|c| instances.get_mut(&c.instance)) {
+            instance.stop(AudioTween::linear(CROSSFADE));
+        }
 
-    // Play BGMs
-    for PlayBgmEvent(bgm) in bgm_events.iter() {
-        if let Some(c) = current {
-            if c.0 == *bgm { return; }
+        // The other mixes start along with the full arrangement to stay in time with it
+        if current.as_ref().map_or(true, |c| c.bgm != bgm) {
+            for (handle, _) in layers.0.drain(..) {
+                if let Some(instance) = instances.get_mut(&handle) {
+                    instance.stop(AudioTween::linear(CROSSFADE));
+                }
+            }
+            if bgm == BGM::Level {
+                layers.0 = audio_assets.level.iter()
+                    .take(audio_assets.level.len() - 1)
+                    .map(|mix| (layer_channel.play(mix.clone()).with_volume(0.).looped().handle(), None))
+                    .collect();
+            }
         }
 
-        commands.insert_resource(CurrentBGM(*bgm));
-        bgm_channel.stop();
-        bgm_channel.set_volume(0.4);
-        bgm_channel
-            .play(bgm.get_handle(&audio_assets.as_ref().unwrap()))
-            .looped();
-        break;
+        let playlist = bgm.playlist(&audio_assets);
+        let track = track % playlist.len();
+        bgm_channel.set_volume(settings.bgm_volume());
+        let mut play = bgm_channel.play(playlist[track].clone());
+        play.fade_in(AudioTween::linear(CROSSFADE));
+        if playlist.len() == 1 { play.looped(); }
+
+        let instance = play.handle();
+        if bgm == BGM::Level { layers.0.push((instance.clone(), None)); }
+
+        *current = Some(CurrentBGM { bgm, track, instance });
+    }
+}
+
+/// Share of a mix heard for the harmony, the garden crossfades from one mix to the next
+fn layer_gain(harmony: f32, layer: usize, layers: usize) -> f64 {
+    (1. - (harmony * (layers - 1) as f32 - layer as f32).abs()).clamp(0., 1.) as f64
+}
+
+fn update_layers(
+    harmony: Res<Harmony>,
+    settings: Res<Settings>,
+    mut layers: ResMut<Layers>,
+    mut instances: ResMut<Assets<AudioInstance>>,
+) {
+    let n = layers.0.len();
+    for (i, (handle, applied)) in layers.0.iter_mut().enumerate() {
+        // The volume of the music channel was just set again
+        if settings.is_changed() { *applied = None; }
+
+        let volume = settings.bgm_volume() * layer_gain(harmony.0, i, n);
+        if *applied == Some(volume) { continue; }
+
+        // Instances show up once they have started playing
+        if let Some(instance) = instances.get_mut(handle) {
+            instance.set_volume(volume, AudioTween::linear(LAYER_FADE));
+            *applied = Some(volume);
+        }
     }
-    bgm_events.clear();
+}
+
+fn play_sfx(
+    mut sfx_events: EventReader<PlaySfxEvent>,
+    audio_assets: Option<Res<AudioAssets>>,
+    tables: Res<Assets<SoundTable>>,
+    effects: Option<Res<Effects>>,
+    sfx_channel: Res<AudioChannel<SfxChannel>>,
+    settings: Res<Settings>,
+    mut playing: Local<Vec<Handle<AudioInstance>>>,
+) {
+    if audio_assets.is_none() || effects.is_none() { return; }
+    let effects = effects.unwrap();
+    let table = tables.get(&audio_assets.unwrap().sounds);
+    if table.is_none() { return; }
+    let table = table.unwrap();
+
+    playing.retain(|handle| !matches!(sfx_channel.state(handle), PlaybackState::Stopped));
 
-    // Play SFXs
     for PlaySfxEvent(sfx) in sfx_events.iter() {
-        sfx_channel.set_volume(0.3);
-        sfx_channel.play(sfx.get_handle(&audio_assets.as_ref().unwrap()));
+        if playing.len() >= table.max_sounds { continue; }
+
+        let sound = table.sounds.get(sfx).or_else(|| sfx.fallback().and_then(|f| table.sounds.get(&f)));
+        if sound.is_none() { continue; }
+        let sound = sound.unwrap();
+        if sound.files.is_empty() { continue; }
+
+        let file = &sound.files[(random::<f32>() * sound.files.len() as f32) as usize % sound.files.len()];
+        if let Some(source) = effects.0.get(file) {
+            let handle = sfx_channel.play(source.clone())
+                .with_volume(settings.sfx_volume() * vary(sound.volume, sound.volume_variation))
+                .with_playback_rate(vary(sound.rate, sound.rate_variation))
+                .handle();
+            playing.push(handle);
+        }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_layer_gain() {
+    assert_eq!(layer_gain(0., 0, 2), 1.);
+    assert_eq!(layer_gain(0., 1, 2), 0.);
+    assert_eq!(layer_gain(0.25, 0, 2), 0.75);
+    assert_eq!(layer_gain(0.25, 1, 2), 0.25);
+    assert_eq!(layer_gain(1., 0, 2), 0.);
+    assert_eq!(layer_gain(1., 1, 2), 1.);
+
+    // Only the neighbouring mixes are heard together
+    assert_eq!(layer_gain(0.25, 2, 3), 0.);
+    assert_eq!(layer_gain(0.5, 1, 3), 1.);
+    // A single mix always plays
+    assert_eq!(layer_gain(0.3, 0, 1), 1.);
+}
+
+#[test]
+fn test_vary() {
+    assert_eq!(vary(0.8, 0.), 0.8);
+    for _ in 0..100 {
+        let v = vary(1., 0.1);
+        assert!(v >= 0.9 && v <= 1.1);
+    }
+}
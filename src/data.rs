@@ -1,15 +1,30 @@
 use bevy::log::error;
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
+use strum::IntoEnumIterator;
 
-use crate::puzzle;
-use crate::puzzle::{Puzzle, Tile};
+use crate::{puzzle, season, solver};
+use crate::puzzle::{Adjacency, Puzzle, Side, Tile};
 use crate::veggie::Veggie;
 
 /// Encode puzzle
 /// - [a-z] x9-  -> 5b (00000 = stop, 00001 = 'a', etc)
 /// - width      -> 5b
 /// - height     -> 4b
-/// - tiles/vegs -> 13b (x 5b + y 4b + 0000=rock, 0001=water, 0010=strawberry, etc)
+/// - tiles/vegs -> 13b (x 5b + y 4b + 0001=water, 0010=rock, 0011=strawberry, etc)
+/// - void tiles -> 13b (x 5b + y 4b + 1011)
+/// - pumpkins   -> 13b (x 5b + y 4b + 1100), at their bottom left tile
+/// - channels   -> 13b (x 5b + y 4b + 1101)
+/// - extended   -> 17b (x 5b + y 4b + 1110 + code 4b), 0000=corn, 0001=bean, 0010=squash, 0011=sunflower,
+///                 0100=fence on the east side of the tile, 0101=fence on its north side,
+///                 0110=water source, 0111=celery,
+///                 1000=harvest target, 1001=par of a scored garden, 1010=move budget,
+///                 1011=par moves, all four stored as x + 32 * y in the position fields
+/// - givens     -> 13b (x 5b + y 4b + 1010)
+/// - options    -> 13b (key 5b + value 4b + 1111), key 0 = adjacency, key 1 = wrap, key 2 = seasons,
+///                 key 3 = at most n sad veggies, key 4 = exactly n sad veggies
+///
+/// Gardens taller than 15 use the wide format: a width of 0 is followed by
+/// width 5b and height 5b, and every y field in the records takes 5b.
 
 pub struct Encoder;
 
@@ -33,25 +48,86 @@ impl Encoder {
             data.append(&mut Encoder::encode_u5((c as u32 - 'a' as u32 + 1) as u8));
         }
         data.append(&mut vec![false, false, false, false, false]);
-        data.append(&mut Encoder::encode_u5(puzzle.size.0 as u8));
-        data.append(&mut Encoder::encode_u4(puzzle.size.1 as u8));
+
+        // Small gardens keep the legacy layout, readable by older versions
+        let wide = puzzle.size.1 > 15;
+        if wide {
+            data.append(&mut Encoder::encode_u5(0));
+            data.append(&mut Encoder::encode_u5(puzzle.size.0 as u8));
+            data.append(&mut Encoder::encode_u5(puzzle.size.1 as u8));
+        } else {
+            data.append(&mut Encoder::encode_u5(puzzle.size.0 as u8));
+            data.append(&mut Encoder::encode_u4(puzzle.size.1 as u8));
+        }
 
         for (&(x, y), tile) in puzzle.tiles.iter() {
             if x >= 0 && y >= 0 && x < puzzle::MAX_W && y < puzzle::MAX_H {
-                data.append(&mut Encoder::encode_u5(x as u8));
-                data.append(&mut Encoder::encode_u4(y as u8));
+                data.append(&mut Encoder::encode_pos(x as u8, y as u8, wide));
                 data.append(&mut Encoder::encode_tile(tile));
             }
         }
 
         for (&(x, y), veg) in puzzle.placed.iter() {
             if x >= 0 && y >= 0 && x < puzzle::MAX_W && y < puzzle::MAX_H {
-                data.append(&mut Encoder::encode_u5(x as u8));
-                data.append(&mut Encoder::encode_u4(y as u8));
+                data.append(&mut Encoder::encode_pos(x as u8, y as u8, wide));
                 data.append(&mut Encoder::encode_veggie(veg));
             }
         }
 
+        for &(x, y) in puzzle.givens.iter() {
+            if puzzle.placed.contains_key(&(x, y)) {
+                data.append(&mut Encoder::encode_pos(x as u8, y as u8, wide));
+                data.append(&mut vec![true, false, true, false]);
+            }
+        }
+
+        if puzzle.adjacency != Adjacency::Eight {
+            let adjacency = Adjacency::iter().position(|a| a == puzzle.adjacency).unwrap();
+            data.append(&mut Encoder::encode_pos(0, adjacency as u8, wide));
+            data.append(&mut vec![true, true, true, true]);
+        }
+
+        if puzzle.wrap {
+            data.append(&mut Encoder::encode_pos(1, 1, wide));
+            data.append(&mut vec![true, true, true, true]);
+        }
+
+        if puzzle.sad > 0 {
+            data.append(&mut Encoder::encode_pos(if puzzle.exact_sad { 4 } else { 3 }, puzzle.sad, wide));
+            data.append(&mut vec![true, true, true, true]);
+        }
+
+        // The target is the harvest of the author's garden
+        if puzzle.seasons > 0 {
+            data.append(&mut Encoder::encode_pos(2, puzzle.seasons, wide));
+            data.append(&mut vec![true, true, true, true]);
+            let harvest = season::harvest(puzzle);
+            data.append(&mut Encoder::encode_count(harvest, wide));
+            data.append(&mut vec![true, true, true, false, true, false, false, false]);
+        }
+
+        // The par is the best score the solver finds
+        if puzzle.scored {
+            let par = solver::par(puzzle);
+            data.append(&mut Encoder::encode_count(par, wide));
+            data.append(&mut vec![true, true, true, false, true, false, false, true]);
+        }
+
+        // The par moves come from the author's garden
+        if puzzle.moves > 0 {
+            data.append(&mut Encoder::encode_count(puzzle.moves, wide));
+            data.append(&mut vec![true, true, true, false, true, false, true, false]);
+            data.append(&mut Encoder::encode_count(solver::par_moves(puzzle), wide));
+            data.append(&mut vec![true, true, true, false, true, false, true, true]);
+        }
+
+        for &((x, y), side) in puzzle.fences.iter() {
+            if x >= 0 && y >= 0 && x < puzzle::MAX_W && y < puzzle::MAX_H {
+                data.append(&mut Encoder::encode_pos(x as u8, y as u8, wide));
+                data.append(&mut vec![true, true, true, false, false, true, false, side == Side::North]);
+            }
+        }
+
         while data.len() % 8 != 0 {
             data.push(false);
         }
@@ -84,10 +160,26 @@ impl Encoder {
         return vec;
     }
 
+    /// x 5b + y 4b, or 5b in the wide format
+    fn encode_pos(x: u8, y: u8, wide: bool) -> Vec<bool> {
+        let mut vec = Encoder::encode_u5(x);
+        vec.append(&mut if wide { Encoder::encode_u5(y) } else { Encoder::encode_u4(y) });
+        return vec;
+    }
+
+    /// x + 32 * y in the position fields, clamped to what they can hold
+    fn encode_count(n: usize, wide: bool) -> Vec<bool> {
+        let n = n.min(if wide { 32 * 32 - 1 } else { 32 * 16 - 1 });
+        Encoder::encode_pos((n % 32) as u8, (n / 32) as u8, wide)
+    }
+
     fn encode_tile(tile: &Tile) -> Vec<bool> {
         match *tile {
             Tile::Water => vec![false, false, false, true],
             Tile::Rock => vec![false, false, true, false],
+            Tile::Void => vec![true, false, true, true],
+            Tile::Channel => vec![true, true, false, true],
+            Tile::Source => vec![true, true, true, false, false, true, true, false],
         }
     }
 
@@ -100,6 +192,12 @@ impl Encoder {
             Veggie::Cherry => vec![false, true, true, true],
             Veggie::Garlic => vec![true, false, false, false],
             Veggie::Mint => vec![true, false, false, true],
+            Veggie::Pumpkin => vec![true, true, false, false],
+            Veggie::Corn => vec![true, true, true, false, false, false, false, false],
+            Veggie::Bean => vec![true, true, true, false, false, false, false, true],
+            Veggie::Squash => vec![true, true, true, false, false, false, true, false],
+            Veggie::Sunflower => vec![true, true, true, false, false, false, true, true],
+            Veggie::Celery => vec![true, true, true, false, false, true, true, true],
         }
     }
 
@@ -131,16 +229,34 @@ impl Decoder {
             author.push(Decoder::decode_char(&char));
         }
 
-        let width = Decoder::decode_u5(&bits.drain(0..5).collect::<Vec<bool>>());
-        let height = Decoder::decode_u4(&bits.drain(0..4).collect::<Vec<bool>>());
+        if bits.len() < 9 { return None; }
+        let mut width = Decoder::decode_u5(&bits.drain(0..5).collect::<Vec<bool>>());
+        let wide = width == 0;
+        let y_len = if wide { 5 } else { 4 };
+        if wide {
+            if bits.len() < 10 { return None; }
+            width = Decoder::decode_u5(&bits.drain(0..5).collect::<Vec<bool>>());
+        }
+        let height = Decoder::decode_y(&bits.drain(0..y_len).collect::<Vec<bool>>());
 
         let mut tiles = HashMap::new();
         let mut placed = HashMap::new();
+        let mut givens = HashSet::new();
+        let mut adjacency = Adjacency::Eight;
+        let mut wrap = false;
+        let mut fences = HashSet::new();
+        let mut seasons = 0;
+        let mut harvest = 0;
+        let mut par = None;
+        let mut moves = 0;
+        let mut par_moves = 0;
+        let mut sad = 0;
+        let mut exact_sad = false;
 
         loop {
-            if bits.len() < 13 { break; }
+            if bits.len() < 9 + y_len { break; }
             let x = Decoder::decode_u5(&bits.drain(0..5).collect::<Vec<bool>>());
-            let y = Decoder::decode_u4(&bits.drain(0..4).collect::<Vec<bool>>());
+            let y = Decoder::decode_y(&bits.drain(0..y_len).collect::<Vec<bool>>());
             let key = (x as i8, y as i8);
             match Decoder::decode_u4(&bits.drain(0..4).collect::<Vec<bool>>()) {
                 1 => { tiles.insert(key, Tile::Water); },
@@ -152,6 +268,36 @@ impl Decoder {
                 7 => { placed.insert(key, Veggie::Cherry); },
                 8 => { placed.insert(key, Veggie::Garlic); },
                 9 => { placed.insert(key, Veggie::Mint); },
+                10 => { givens.insert(key); },
+                11 => { tiles.insert(key, Tile::Void); },
+                12 => { placed.insert(key, Veggie::Pumpkin); },
+                13 => { tiles.insert(key, Tile::Channel); },
+                14 => {
+                    if bits.len() < 4 { return None; }
+                    match Decoder::decode_u4(&bits.drain(0..4).collect::<Vec<bool>>()) {
+                        0 => { placed.insert(key, Veggie::Corn); },
+                        1 => { placed.insert(key, Veggie::Bean); },
+                        2 => { placed.insert(key, Veggie::Squash); },
+                        3 => { placed.insert(key, Veggie::Sunflower); },
+                        4 => { fences.insert((key, Side::East)); },
+                        5 => { fences.insert((key, Side::North)); },
+                        6 => { tiles.insert(key, Tile::Source); },
+                        7 => { placed.insert(key, Veggie::Celery); },
+                        8 => { harvest = x as usize + 32 * y as usize; },
+                        9 => { par = Some(x as usize + 32 * y as usize); },
+                        10 => { moves = x as usize + 32 * y as usize; },
+                        11 => { par_moves = x as usize + 32 * y as usize; },
+                        _ => { error!("Couldn't parse extended veg :("); return None; },
+                    }
+                },
+                15 => match (x, y, Adjacency::iter().nth(y as usize)) {
+                    (0, _, Some(a)) => { adjacency = a; },
+                    (1, 1, _) => { wrap = true; },
+                    (2, n, _) if n <= season::MAX_SEASONS => { seasons = n; },
+                    (3, n, _) => { sad = n; },
+                    (4, n, _) => { sad = n; exact_sad = true; },
+                    _ => { error!("Couldn't parse option :("); return None; },
+                },
                 _ => { error!("Couldn't parse tile/veg :("); return None; },
             }
         }
@@ -162,11 +308,23 @@ impl Decoder {
             veggies: HashMap::new(),
             tiles,
             placed: placed.clone(),
+            givens,
+            adjacency,
+            wrap,
+            fences,
+            seasons,
+            harvest,
+            scored: par.is_some(),
+            par: par.unwrap_or(0),
+            moves,
+            par_moves,
+            sad,
+            exact_sad,
         };
 
         match puzzle.is_valid() {
             Ok(_) => {
-                for (_, veg) in placed.iter() {
+                for (_, veg) in placed.iter().filter(|(p, _)| !puzzle.givens.contains(*p)) {
                     if puzzle.veggies.contains_key(veg) {
                         *puzzle.veggies.get_mut(veg).unwrap() += 1;
                     } else {
@@ -213,6 +371,11 @@ impl Decoder {
         return result;
     }
 
+    /// 4b, or 5b in the wide format
+    fn decode_y(slice: &[bool]) -> u8 {
+        if slice.len() == 5 { Decoder::decode_u5(slice) } else { Decoder::decode_u4(slice) }
+    }
+
     fn decode_char(slice: &[bool]) -> char {
         return (Decoder::decode_u5(slice) - 1 + 'a' as u8) as char;
     }
@@ -248,4 +411,109 @@ pub fn write_level(s: String) {
     if let Some(window) = web_sys::window() {
         window.alert_with_message(&format!("Your level code is: ```{}```", s));
     }
-}
\ No newline at end of file
+}
+
+/// Fields written in the level code, the others are derived when decoding
+#[cfg(test)]
+fn encoded_fields(puzzle: &Puzzle) -> impl PartialEq + std::fmt::Debug {
+    (
+        (puzzle.size, puzzle.tiles.clone(), puzzle.placed.clone(), puzzle.givens.clone(), puzzle.fences.clone()),
+        (puzzle.adjacency.clone(), puzzle.wrap, puzzle.seasons, puzzle.scored, puzzle.moves, puzzle.sad, puzzle.exact_sad),
+    )
+}
+
+#[test]
+fn test_round_trip() {
+    // One garden per feature of the format, then a check of what the decoder derives from it
+    let cases: [(&str, fn(&mut Puzzle), fn(&Puzzle)); 9] = [
+        ("wide", |puzzle| {
+            puzzle.size = (puzzle::MAX_W, puzzle::MAX_H);
+            puzzle.tiles.insert((0, 19), Tile::Rock);
+            puzzle.placed.insert((19, 18), Veggie::Strawberry);
+            puzzle.placed.insert((19, 19), Veggie::Strawberry);
+            puzzle.givens.insert((19, 19));
+            puzzle.adjacency = Adjacency::Four;
+            puzzle.wrap = true;
+        }, |_| {}),
+        ("pumpkin", |puzzle| {
+            puzzle.size = (4, 3);
+            puzzle.placed.insert((1, 1), Veggie::Pumpkin);
+        }, |decoded| assert_eq!(decoded.veggies.get(&Veggie::Pumpkin), Some(&1))),
+        ("extended codes", |puzzle| {
+            puzzle.placed.insert((0, 0), Veggie::Corn);
+            puzzle.placed.insert((1, 0), Veggie::Bean);
+            puzzle.placed.insert((1, 1), Veggie::Squash);
+            puzzle.tiles.insert((4, 2), Tile::Rock);
+        }, |_| {}),
+        ("fences", |puzzle| {
+            puzzle.placed.insert((0, 0), Veggie::Strawberry);
+            puzzle.placed.insert((0, 1), Veggie::Strawberry);
+            puzzle.fences.insert(((0, 0), Side::East));
+            puzzle.fences.insert(((3, 1), Side::North));
+        }, |_| {}),
+        ("irrigation", |puzzle| {
+            puzzle.placed.insert((2, 1), Veggie::Celery);
+            puzzle.tiles.insert((0, 0), Tile::Source);
+            puzzle.tiles.insert((1, 0), Tile::Channel);
+            puzzle.tiles.insert((2, 0), Tile::Channel);
+        }, |_| {}),
+        ("seasons", |puzzle| {
+            puzzle.placed.insert((0, 0), Veggie::Strawberry);
+            puzzle.placed.insert((1, 0), Veggie::Strawberry);
+            puzzle.seasons = 2;
+        }, |decoded| assert_eq!(decoded.harvest, season::harvest(decoded))),
+        ("scored", |puzzle| {
+            puzzle.size = (3, 1);
+            puzzle.placed.insert((0, 0), Veggie::Tomato);
+            puzzle.placed.insert((2, 0), Veggie::Garlic);
+            puzzle.scored = true;
+        }, |decoded| {
+            // The author's garden doesn't have to be the best one
+            assert_eq!(decoded.par, 8);
+        }),
+        ("tolerance", |puzzle| {
+            puzzle.placed.insert((0, 0), Veggie::Strawberry);
+            puzzle.sad = 1;
+            puzzle.exact_sad = true;
+        }, |_| {}),
+        ("moves", |puzzle| {
+            puzzle.placed.insert((0, 0), Veggie::Strawberry);
+            puzzle.placed.insert((1, 0), Veggie::Strawberry);
+            puzzle.moves = 40;
+        }, |decoded| assert_eq!(decoded.par_moves, 2)),
+    ];
+
+    for (name, setup, check) in cases {
+        let mut puzzle = Puzzle::default();
+        setup(&mut puzzle);
+        let decoded = Decoder::decode_puzzle(Encoder::encode_puzzle(&puzzle).unwrap()).unwrap();
+        assert_eq!(encoded_fields(&decoded), encoded_fields(&puzzle), "{name}");
+        check(&decoded);
+    }
+}
+
+#[test]
+fn test_legacy_format() {
+    // Small gardens keep the legacy layout
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (10, 7);
+    puzzle.placed = [((9, 5), Veggie::Strawberry), ((9, 6), Veggie::Strawberry)].into_iter().collect();
+    let encoded = Encoder::encode_puzzle(&puzzle).unwrap();
+    let decoded = Decoder::decode_puzzle(encoded.clone()).unwrap();
+    assert_eq!(decoded.size, (10, 7));
+    assert_eq!(decoded.placed, puzzle.placed);
+
+    puzzle.size = (10, 16);
+    let wide = Encoder::encode_puzzle(&puzzle).unwrap();
+    // Sizes in bytes, the base91 text length also depends on the data
+    let size = |code: &String| base91::slice_decode(code.as_bytes()).len();
+    assert!(size(&encoded) < size(&wide));
+    let decoded = Decoder::decode_puzzle(wide).unwrap();
+    assert_eq!(decoded.size, (10, 16));
+
+    // Budgets too large for the legacy layout are clamped
+    puzzle.size = (10, 7);
+    puzzle.moves = 600;
+    let decoded = Decoder::decode_puzzle(Encoder::encode_puzzle(&puzzle).unwrap()).unwrap();
+    assert_eq!(decoded.moves, 32 * 16 - 1);
+}
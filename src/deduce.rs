@@ -0,0 +1,449 @@
+use bevy::utils::{HashMap, HashSet};
+use strum::IntoEnumIterator;
+
+use crate::{puzzle, solver};
+use crate::puzzle::{Puzzle, Tile};
+use crate::veggie::Veggie;
+
+/// Why a deduction holds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    /// The veggie would be next to a tile it hates
+    Terrain(Tile, (i8, i8)),
+    /// The veggie would bother an adjacent veggie
+    Bothers(Veggie, (i8, i8)),
+    /// An adjacent veggie would bother the veggie
+    BotheredBy(Veggie, (i8, i8)),
+    /// Cherries and apple trees can't share a line or a column
+    Jealous(Veggie, (i8, i8)),
+    /// The adjacent cherry already has its pair
+    Paired((i8, i8)),
+    /// Only spot left to make an adjacent veggie happy
+    OnlySpot(Veggie, (i8, i8)),
+    /// As many spots left as veggies (or empty tiles) to place
+    Count(usize),
+    /// Nothing else fits on the tile
+    LastOption,
+    /// The tiles a big veggie would cover aren't free
+    NoRoom,
+    /// The veggie would be in the shadow of a sunflower
+    Shade((i8, i8)),
+    /// The veggie would be watered (`true`) or dry (`false`)
+    Water(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// `None` means the tile must be planted
+    Exclude(Option<Veggie>),
+    /// `None` means the tile stays empty
+    Place(Option<Veggie>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub pos: (i8, i8),
+    pub action: Action,
+    pub reason: Reason,
+}
+
+fn at(pos: (i8, i8)) -> String {
+    format!("({},{})", pos.0 + 1, pos.1 + 1)
+}
+
+impl Step {
+    pub fn explain(&self) -> String {
+        let here = at(self.pos);
+        match (&self.action, &self.reason) {
+            (Action::Exclude(Some(v)), Reason::Terrain(t, p)) =>
+                format!("{} can't go at {}: next to {} at {}", v.name(), here, t.name(), at(*p)),
+            (Action::Exclude(Some(v)), Reason::Bothers(n, p)) =>
+                format!("{} can't go at {}: it would bother the {} at {}", v.name(), here, n.name(), at(*p)),
+            (Action::Exclude(Some(v)), Reason::BotheredBy(n, p)) =>
+                format!("{} can't go at {}: the {} at {} would bother it", v.name(), here, n.name(), at(*p)),
+            (Action::Exclude(Some(v)), Reason::Jealous(n, p)) =>
+                format!("{} can't go at {}: same line or column as the {} at {}", v.name(), here, n.name(), at(*p)),
+            (Action::Exclude(Some(v)), Reason::Paired(p)) =>
+                format!("{} can't go at {}: the cherry at {} already has a pair", v.name(), here, at(*p)),
+            (Action::Exclude(Some(v)), Reason::Shade(p)) =>
+                format!("{} can't go at {}: in the shadow of the sunflower at {}", v.name(), here, at(*p)),
+            (Action::Exclude(Some(v)), Reason::Water(true)) =>
+                format!("{} can't go at {}: the tile is watered", v.name(), here),
+            (Action::Exclude(Some(v)), Reason::Water(false)) =>
+                format!("{} can't go at {}: no water around", v.name(), here),
+            (Action::Exclude(Some(v)), Reason::NoRoom) =>
+                format!("{} can't go at {}: not enough room", v.name(), here),
+            (Action::Exclude(Some(v)), Reason::OnlySpot(n, p)) =>
+                format!("{} can't go at {}: it's the only spot left to make the {} at {} happy", v.name(), here, n.name(), at(*p)),
+            (Action::Place(Some(Veggie::Cherry)), Reason::OnlySpot(Veggie::Cherry, p)) =>
+                format!("the two cherries must pair up here: {} and {}", here, at(*p)),
+            (Action::Place(Some(v)), Reason::OnlySpot(n, p)) =>
+                format!("{} must go at {}: it's the only spot left to make the {} at {} happy", v.name(), here, n.name(), at(*p)),
+            (Action::Place(Some(v)), Reason::Count(n)) =>
+                format!("{} must go at {}: {} left to plant and {} spots left for them", v.name(), here, n, n),
+            (Action::Place(None), Reason::Count(n)) =>
+                format!("{} must stay empty: {} tiles must stay empty and only {} can", here, n, n),
+            (Action::Exclude(None), Reason::Count(_)) =>
+                format!("{} can't stay empty: every tile left must be planted", here),
+            (Action::Place(Some(v)), _) => format!("only {} fits at {}", v.name(), here),
+            (Action::Place(None), _) => format!("{} must stay empty: nothing fits there", here),
+            (Action::Exclude(Some(v)), _) => format!("{} can't go at {}", v.name(), here),
+            (Action::Exclude(None), _) => format!("{} can't stay empty", here),
+        }
+    }
+}
+
+/// Applies human-style deductions to a partially filled puzzle, one step at a time.
+pub struct Deducer {
+    puzzle: Puzzle,
+    /// What the undecided tiles can still hold
+    candidates: HashMap<(i8, i8), Vec<Option<Veggie>>>,
+}
+
+impl Deducer {
+    pub fn new(puzzle: &Puzzle) -> Self {
+        let mut options = vec![None];
+        options.extend(Veggie::iter().filter(|v| puzzle.remaining_veggie(v, false) > 0).map(Some));
+
+        let candidates = puzzle.free_tiles()
+            .into_iter()
+            .map(|pos| (pos, options.clone()))
+            .collect();
+
+        Deducer { puzzle: puzzle.clone(), candidates }
+    }
+
+    /// Every tile is decided and the veggies are happy.
+    pub fn is_solved(&self) -> bool {
+        self.candidates.is_empty()
+            && self.puzzle.veggies.keys().all(|v| self.puzzle.remaining_veggie(v, false) == 0)
+            && self.puzzle.is_valid().is_ok()
+    }
+
+    /// Applies deductions until none is left.
+    pub fn run(&mut self) -> Vec<Step> {
+        let mut steps = vec![];
+        while let Some(step) = self.next_step() {
+            self.apply(&step);
+            steps.push(step);
+        }
+        steps
+    }
+
+    pub fn apply(&mut self, step: &Step) {
+        match step.action {
+            Action::Exclude(option) => {
+                if let Some(c) = self.candidates.get_mut(&step.pos) {
+                    c.retain(|o| *o != option);
+                }
+            }
+            Action::Place(option) => {
+                self.candidates.remove(&step.pos);
+                if let Some(veggie) = option {
+                    for p in self.puzzle.footprint(&veggie, step.pos) {
+                        self.candidates.remove(&p);
+                    }
+                    self.puzzle.placed.insert(step.pos, veggie);
+                    if self.remaining(&option) == 0 {
+                        self.candidates.values_mut().for_each(|c| c.retain(|o| *o != option));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Easiest deduction available, if any.
+    pub fn next_step(&self) -> Option<Step> {
+        // The deductions rely on every veggie being happy
+        if !self.puzzle.strict() { return None; }
+        let undecided = self.undecided();
+
+        // Only one option left
+        for pos in undecided.iter() {
+            if let [option] = self.candidates[pos][..] {
+                if option.is_none() && self.may_be_covered(*pos) { continue; }
+                return Some(Step { pos: *pos, action: Action::Place(option), reason: Reason::LastOption });
+            }
+        }
+
+        // Veggies that can't go somewhere because of their surroundings
+        for pos in undecided.iter() {
+            for option in self.candidates[pos].iter() {
+                if let Some(veggie) = option {
+                    if let Some(reason) = self.exclusion(veggie, *pos) {
+                        return Some(Step { pos: *pos, action: Action::Exclude(*option), reason });
+                    }
+                }
+            }
+        }
+
+        // Veggies that need a specific neighbour
+        for (pos, veggie) in self.placed_in_order() {
+            if let Some(step) = self.need(&veggie, pos) {
+                return Some(step);
+            }
+        }
+
+        // As many spots as veggies to place
+        let mut options = vec![None];
+        options.extend(Veggie::iter().map(Some));
+        for option in options {
+            // Empty tiles can't be counted while big veggies are left to plant
+            if option.is_none() && Veggie::iter().any(|v| v.span() > 1 && self.remaining(&Some(v)) > 0) { continue; }
+            let needed = self.remaining(&option);
+            let spots = undecided.iter().filter(|p| self.candidates[*p].contains(&option)).collect::<Vec<_>>();
+            if spots.is_empty() { continue; }
+
+            if needed == spots.len() {
+                return Some(Step { pos: *spots[0], action: Action::Place(option), reason: Reason::Count(needed) });
+            } else if needed == 0 && option.is_none() {
+                return Some(Step { pos: *spots[0], action: Action::Exclude(None), reason: Reason::Count(0) });
+            }
+        }
+
+        None
+    }
+
+    fn undecided(&self) -> Vec<(i8, i8)> {
+        let mut undecided = self.candidates.keys().copied().collect::<Vec<_>>();
+        undecided.sort_by_key(|(x, y)| (*y, *x));
+        undecided
+    }
+
+    fn placed_in_order(&self) -> Vec<((i8, i8), Veggie)> {
+        let mut placed = self.puzzle.placed.iter().map(|(p, v)| (*p, *v)).collect::<Vec<_>>();
+        placed.sort_by_key(|((x, y), _)| (*y, *x));
+        placed
+    }
+
+    /// Veggies left to plant, or empty tiles left for `None`.
+    fn remaining(&self, option: &Option<Veggie>) -> usize {
+        match option {
+            Some(v) => self.puzzle.remaining_veggie(v, false),
+            None => {
+                let veggies = Veggie::iter()
+                    .map(|v| self.puzzle.remaining_veggie(&v, false) * v.footprint().len())
+                    .sum::<usize>();
+                self.candidates.len().saturating_sub(veggies)
+            }
+        }
+    }
+
+    /// A big veggie could still be planted over the tile.
+    fn may_be_covered(&self, pos: (i8, i8)) -> bool {
+        self.candidates.iter().any(|(p, c)| c.iter().flatten().any(|v| {
+            v.span() > 1 && *p != pos && self.puzzle.footprint(v, *p).contains(&pos)
+        }))
+    }
+
+    fn exclusion(&self, veggie: &Veggie, pos: (i8, i8)) -> Option<Reason> {
+        if veggie.span() > 1 {
+            let room = self.puzzle.fits(veggie, pos) && self.puzzle.footprint(veggie, pos)
+                .iter()
+                .all(|p| *p == pos || self.candidates.get(p).map_or(false, |c| c.contains(&None)));
+            if !room { return Some(Reason::NoRoom); }
+        }
+
+        let around = self.puzzle.around(veggie, pos);
+        if let Some(hated) = puzzle::hated_tile(veggie) {
+            if let Some(p) = around.iter().find(|p| self.puzzle.tiles.get(p) == Some(&hated)) {
+                return Some(Reason::Terrain(hated, *p));
+            }
+        }
+
+        match veggie {
+            Veggie::Garlic if self.puzzle.is_watered(veggie, pos) => return Some(Reason::Water(true)),
+            Veggie::Celery if !self.puzzle.is_watered(veggie, pos) => return Some(Reason::Water(false)),
+            _ => {}
+        }
+
+        for p in around {
+            if let Some((p, n)) = self.puzzle.occupant(p) {
+                if puzzle::unhappy_adjacent(n).contains(veggie) { return Some(Reason::Bothers(*n, p)); }
+                if puzzle::unhappy_adjacent(veggie).contains(n) { return Some(Reason::BotheredBy(*n, p)); }
+            }
+        }
+
+        if puzzle::needs_sun(veggie) {
+            if let Some(p) = self.puzzle.shaded_by(veggie, pos) { return Some(Reason::Shade(p)); }
+        }
+        if let Some((p, n)) = self.puzzle.shades(veggie, pos) {
+            return Some(Reason::Bothers(*n, p));
+        }
+
+        if let Some((p, n)) = self.puzzle.crowding(veggie, pos) {
+            return Some(if puzzle::needs_room(veggie) { Reason::BotheredBy(*n, p) } else { Reason::Bothers(*n, p) });
+        }
+
+        let rival = match veggie {
+            Veggie::Cherry => Some(Veggie::Apple),
+            Veggie::Apple => Some(Veggie::Cherry),
+            _ => None,
+        };
+        if let Some(rival) = rival {
+            if let Some((p, _)) = self.placed_in_order().into_iter().find(|(p, v)| *v == rival && self.puzzle.in_line(*p, pos)) {
+                return Some(Reason::Jealous(rival, p));
+            }
+        }
+
+        if *veggie == Veggie::Cherry {
+            for p in self.puzzle.neighbours(pos) {
+                if self.puzzle.placed.get(&p) == Some(&Veggie::Cherry)
+                    && self.puzzle.adjacent(p, &self.puzzle.placed).contains(&&Veggie::Cherry) {
+                    return Some(Reason::Paired(p));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn need(&self, veggie: &Veggie, pos: (i8, i8)) -> Option<Step> {
+        // Each group needs one of its veggies around
+        let groups = match veggie {
+            Veggie::Strawberry => vec![vec![Veggie::Strawberry]],
+            Veggie::Cherry => vec![vec![Veggie::Cherry]],
+            Veggie::Tomato => vec![vec![Veggie::Garlic, Veggie::Carrot]],
+            Veggie::Corn | Veggie::Bean | Veggie::Squash => puzzle::sisters(veggie).into_iter().map(|v| vec![v]).collect(),
+            _ => return None,
+        };
+
+        let adjacent = self.puzzle.adjacent_veggies(veggie, pos);
+        for wanted in groups {
+            if wanted.iter().any(|w| adjacent.contains(&w)) { continue; }
+
+            let spots = self.puzzle.around(veggie, pos)
+                .into_iter()
+                .filter(|p| self.candidates.get(p).map_or(false, |c| wanted.iter().any(|w| c.contains(&Some(*w)))))
+                .collect::<Vec<_>>();
+            if spots.len() != 1 { continue; }
+
+            let spot = spots[0];
+            let reason = Reason::OnlySpot(*veggie, pos);
+            if let [w] = wanted[..] {
+                return Some(Step { pos: spot, action: Action::Place(Some(w)), reason });
+            }
+
+            let step = self.candidates[&spot]
+                .iter()
+                .find(|o| !o.map_or(false, |v| wanted.contains(&v)))
+                .map(|o| Step { pos: spot, action: Action::Exclude(*o), reason });
+            if step.is_some() { return step; }
+        }
+
+        None
+    }
+}
+
+/// What the editor tells about a level: how many solutions it has, whether deductions
+/// alone solve it, with how many kinds of them, and the first one.
+pub fn review(level: &Puzzle) -> String {
+    // The deductions rely on every veggie being happy
+    if !level.strict() { return String::new(); }
+
+    // The search may give up on large gardens before it is sure
+    let solutions = match solver::count_solutions(level, 2) {
+        (0, true) => "no solution",
+        (0, false) => "solutions not counted",
+        (1, true) => "one solution",
+        (1, false) => "at least one solution",
+        _ => "several solutions",
+    };
+
+    let mut deducer = Deducer::new(level);
+    let steps = deducer.run();
+    let kinds = steps.iter().map(|s| std::mem::discriminant(&s.reason)).collect::<HashSet<_>>();
+
+    let deductions = if deducer.is_solved() {
+        format!("solvable without guessing in {} steps ({} kinds)", steps.len(), kinds.len())
+    } else {
+        format!("needs a guess after {} steps", steps.len())
+    };
+    let first = steps.first().map_or("no deduction to start with".to_string(), |s| s.explain());
+
+    format!("{}, {}\n{}", solutions, deductions, first)
+}
+
+#[test]
+fn test_deduce_terrain() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.tiles.insert((0, 0), Tile::Water);
+    puzzle.veggies.insert(Veggie::Garlic, 1);
+
+    let mut deducer = Deducer::new(&puzzle);
+    let steps = deducer.run();
+
+    assert_eq!(steps[0].explain(), "garlic can't go at (2,1): next to water at (1,1)");
+    assert!(deducer.is_solved());
+    assert_eq!(deducer.puzzle.placed.get(&(2, 0)), Some(&Veggie::Garlic));
+}
+
+#[test]
+fn test_deduce_cherry_pair() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.veggies.insert(Veggie::Cherry, 2);
+    puzzle.placed.insert((0, 0), Veggie::Cherry);
+
+    let step = Deducer::new(&puzzle).next_step().unwrap();
+
+    assert_eq!(step.pos, (1, 0));
+    assert_eq!(step.action, Action::Place(Some(Veggie::Cherry)));
+    assert_eq!(step.explain(), "the two cherries must pair up here: (2,1) and (1,1)");
+}
+
+#[test]
+fn test_deduce_guessing() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.veggies.insert(Veggie::Strawberry, 2);
+
+    // Two solutions: no deduction can pick one
+    let mut deducer = Deducer::new(&puzzle);
+    deducer.run();
+    assert!(!deducer.is_solved());
+    assert_eq!(review(&puzzle), "several solutions, needs a guess after 0 steps\nno deduction to start with");
+}
+
+#[test]
+fn test_review() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.tiles.insert((0, 0), Tile::Water);
+    puzzle.veggies.insert(Veggie::Garlic, 1);
+
+    assert_eq!(review(&puzzle), "one solution, solvable without guessing in 4 steps (3 kinds)\n\
+        garlic can't go at (2,1): next to water at (1,1)");
+
+    puzzle.scored = true;
+    assert_eq!(review(&puzzle), "");
+}
+
+#[test]
+fn test_deduce_pumpkin() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 2);
+    puzzle.tiles.insert((0, 0), Tile::Rock);
+    puzzle.veggies.insert(Veggie::Pumpkin, 1);
+
+    let mut deducer = Deducer::new(&puzzle);
+    let steps = deducer.run();
+
+    assert_eq!(steps[0].explain(), "pumpkin can't go at (3,1): not enough room");
+    assert!(deducer.is_solved());
+    assert_eq!(deducer.puzzle.placed.get(&(1, 0)), Some(&Veggie::Pumpkin));
+}
+
+#[test]
+fn test_deduce_shade() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (1, 3);
+    puzzle.placed.insert((0, 0), Veggie::Sunflower);
+    puzzle.veggies.insert(Veggie::Tomato, 1);
+    puzzle.veggies.insert(Veggie::Garlic, 1);
+    puzzle.givens.insert((0, 0));
+
+    let steps = Deducer::new(&puzzle).run();
+    assert_eq!(steps[0].explain(), "tomato can't go at (1,2): in the shadow of the sunflower at (1,1)");
+}
@@ -2,15 +2,18 @@ use std::ops::Add;
 
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
 use bevy::sprite::Anchor;
 use bevy_text_mode::{TextModeSpriteSheetBundle, TextModeTextureAtlasSprite};
 use bevy_tweening::Animator;
 use strum::IntoEnumIterator;
 
-use crate::{data, GameState, HEIGHT, puzzle, util, WIDTH};
+use crate::{BlockInput, data, deduce, GameState, GardenCamera, HEIGHT, MainCamera, puzzle, season, solver, util, WIDTH};
 use crate::audio::{BGM, PlayBgmEvent, PlaySfxEvent, SFX};
 use crate::data::{Decoder, Encoder};
-use crate::grid::{CurrentPuzzle, DisplayLevel, GridChanged, GridTile, PreviousPos};
+use crate::grid::{CurrentPuzzle, DisplayLevel, garden_layer, get_pos_at, get_tile_pos, GridChanged, GridTile, GridVeggie, PreviousPos};
+use crate::input::{Action, Actions, Clickable, Pointer};
+use crate::inventory::Held;
 use crate::loading::Textures;
 use crate::puzzle::{Puzzle, Tile};
 use crate::text::{ButtonClick, spawn_text, TextButtonId};
@@ -21,14 +24,19 @@ pub struct EditorPlugin;
 impl Plugin for EditorPlugin {
     fn build(&self, app: &mut App) {
         app
+            .init_resource::<FenceTool>()
+            .init_resource::<Review>()
             .add_system_set(SystemSet::on_enter(GameState::Editor)
                 .with_system(play_music)
+                .with_system(reset_tool)
             )
             .add_system_set(SystemSet::on_update(GameState::Editor)
                 .with_system(display_editor)
                 .with_system(handle_click)
                 .with_system(handle_drop)
                 .with_system(handle_click_on_grid)
+                .with_system(toggle_given)
+                .with_system(handle_fence_click)
                 .with_system(update_author)
                 .with_system(click_on_button.after("logic"))
             )
@@ -42,21 +50,42 @@ struct EditorUI;
 #[derive(Component)]
 struct EditorTile(Tile);
 
+/// Tiles per column, to stay above the buttons
+const TILE_ROWS: usize = 4;
+
 #[derive(Component)]
 struct AuthorName;
 
+/// Clicks on the garden put up fences instead of moving veggies and tiles
+#[derive(Resource, Default)]
+pub struct FenceTool(pub bool);
+
+/// What the deducer and the solver tell about the level last saved
+#[derive(Resource, Default)]
+struct Review(String);
+
 fn play_music(
    mut bgm: EventWriter<PlayBgmEvent>,
 ) {
     bgm.send(PlayBgmEvent(BGM::Editor));
 }
 
+fn reset_tool(
+    mut fence_tool: ResMut<FenceTool>,
+    mut review: ResMut<Review>,
+) {
+    fence_tool.0 = false;
+    review.0.clear();
+}
+
 fn display_editor(
     mut commands: Commands,
     textures: Res<Textures>,
     puzzle: Res<CurrentPuzzle>,
     mut display_event: EventReader<DisplayLevel>,
     entities: Query<Entity, With<EditorUI>>,
+    fence_tool: Res<FenceTool>,
+    review: Res<Review>,
 ) {
     if puzzle.0.is_none() { return; }
     let puzzle = puzzle.0.as_ref().unwrap();
@@ -66,13 +95,15 @@ fn display_editor(
 
         // Tiles
         let tiles = Tile::iter().collect::<Vec<Tile>>();
-        let h = (HEIGHT - tiles.len() as f32 * 48.) / 2. + 48.;
-        let w = WIDTH - 32. - 48.;
+        let rows = TILE_ROWS.min(tiles.len());
+        let columns = (tiles.len() + rows - 1) / rows;
+        let h = (HEIGHT - rows as f32 * 48.) / 2. + 48.;
+        let w = WIDTH - 32. - 48. * columns as f32;
 
         let id = util::frame(
             &mut commands, &textures,
             w - 16., h - 8., util::z::VEG_UI_BG,
-            9, 14,
+            3 + 6 * columns, rows * 6 + 2,
             Colors::DarkRed, Colors::Beige
         );
         commands.entity(id).insert(EditorUI);
@@ -86,18 +117,16 @@ fn display_editor(
                         ..Default::default()
                     },
                     texture_atlas: textures.tile.clone(),
-                    transform: Transform::from_xyz(w, h + 48. * i as f32 + 4., util::z::VEG_UI),
+                    transform: Transform::from_xyz(w + 48. * (i / rows) as f32, h + 48. * (i % rows) as f32 + 4., util::z::VEG_UI),
                     ..Default::default()
                 })
                 .insert(EditorUI)
-                .insert(EditorTile(tile.clone()));
+                .insert(EditorTile(tile.clone()))
+                .insert(Clickable::square(40.));
         }
 
         // Buttons
-        let grid_w = puzzle.size.0 as f32 * 40.;
-        let grid_x = (WIDTH - grid_w) / 2.;
-        let grid_h = puzzle.size.1 as f32 * 40.;
-        let grid_y = (HEIGHT - grid_h) / 2.;
+        let moves = if puzzle.moves > 0 { format!("moves: {:0>2}", puzzle.moves) } else { "moves: off".to_string() };
 
         #[cfg(target_arch = "wasm32")]
             let save =      "- save  -\n  level  ";
@@ -110,10 +139,11 @@ fn display_editor(
             let load = "load from\nclipboard";
 
         for (x, y, text, bg, fg, button) in [
-            (grid_x, grid_y + grid_h + 8., "+", Colors::Green, Colors::Beige, TextButtonId::ExpandShrink(true, true)),
-            (grid_x + 12., grid_y + grid_h + 8., "-", Colors::Red, Colors::Beige, TextButtonId::ExpandShrink(false, true)),
-            (grid_x + grid_w + 8., grid_y + grid_h - 8., "+", Colors::Green, Colors::Beige, TextButtonId::ExpandShrink(true, false)),
-            (grid_x + grid_w + 8., grid_y + grid_h - 20., "-", Colors::Red, Colors::Beige, TextButtonId::ExpandShrink(false, false)),
+            (176., 20., "-", Colors::Red, Colors::Beige, TextButtonId::ExpandShrink(false, false)),
+            (188., 20., "+", Colors::Green, Colors::Beige, TextButtonId::ExpandShrink(true, false)),
+            (264., 20., "-", Colors::Red, Colors::Beige, TextButtonId::ExpandShrink(false, true)),
+            (276., 20., "+", Colors::Green, Colors::Beige, TextButtonId::ExpandShrink(true, true)),
+            (WIDTH - 96., 62. + 40., &moves, Colors::Beige, Colors::DarkRed, TextButtonId::Moves),
             (WIDTH - 96., 62. + 16., save, Colors::Beige, Colors::DarkRed, TextButtonId::Export),
             (WIDTH - 96., 62. - 8., load, Colors::Beige, Colors::DarkRed, TextButtonId::Import),
             (WIDTH - 96., 62. - 32., "- clear -", Colors::Beige, Colors::DarkRed, TextButtonId::Clear),
@@ -134,6 +164,74 @@ fn display_editor(
                 .insert(EditorUI);
         }
 
+        // Size and rules
+        for (x, text) in [(128., "width"), (208., "height")] {
+            let id = spawn_text(
+                &mut commands,
+                &textures,
+                Vec3::new(x, 20., util::z::VEG_UI),
+                text,
+                Colors::DarkRed,
+                Colors::Beige,
+            );
+            commands.entity(id).insert(EditorUI);
+        }
+
+        for (x, text, button) in [
+            (296., format!("seasons: {}", puzzle.seasons), TextButtonId::Seasons),
+            (384., format!("score: {}", if puzzle.scored { "on" } else { "off" }), TextButtonId::Scored),
+            (472., format!("sad: {}", match (puzzle.sad, puzzle.exact_sad) {
+                (0, _) => "0".to_string(),
+                (n, true) => format!("={}", n),
+                (n, false) => format!("<={}", n),
+            }), TextButtonId::Tolerance),
+        ] {
+            let id = spawn_text(
+                &mut commands,
+                &textures,
+                Vec3::new(x, 20., util::z::VEG_UI),
+                &text,
+                Colors::DarkRed,
+                Colors::Beige,
+            );
+            commands
+                .entity(id)
+                .insert(button)
+                .insert(EditorUI);
+        }
+
+        for (x, text, button) in [
+            (128., format!("neighbours: {}", puzzle.adjacency.label()), TextButtonId::Adjacency),
+            (312., format!("edges: {}", if puzzle.wrap { "wrap" } else { "walls" }), TextButtonId::Wrap),
+            (424., format!("fences: {}", if fence_tool.0 { "on" } else { "off" }), TextButtonId::FenceTool),
+        ] {
+            let id = spawn_text(
+                &mut commands,
+                &textures,
+                Vec3::new(x, 8., util::z::VEG_UI),
+                &text,
+                Colors::DarkRed,
+                Colors::Beige,
+            );
+            commands
+                .entity(id)
+                .insert(button)
+                .insert(EditorUI);
+        }
+
+        // Above the garden
+        if !review.0.is_empty() {
+            let id = spawn_text(
+                &mut commands,
+                &textures,
+                Vec3::new(8., HEIGHT - 12., util::z::VEG_UI),
+                &review.0,
+                Colors::DarkRed,
+                Colors::Beige,
+            );
+            commands.entity(id).insert(EditorUI);
+        }
+
         let id = spawn_text(
             &mut commands,
             &textures,
@@ -197,19 +295,18 @@ pub struct DraggedTile(pub Tile);
 
 fn handle_click(
     mut commands: Commands,
-    inventory: Query<(&EditorTile, &Transform)>,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    inventory: Query<(&EditorTile, &Clickable)>,
+    actions: Res<Actions>,
+    pointer: Res<Pointer>,
     textures: Res<Textures>,
+    block_input: Res<BlockInput>,
     mut sfx: EventWriter<PlaySfxEvent>,
 ) {
-    if mouse.just_pressed(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
-            if let Some((e, _)) = inventory.iter().filter(|(_, t)|
-                (t.translation.x + 20. - pos.x / 2.).abs() < 20.
-                    && (t.translation.y + 20. - pos.y / 2.).abs() < 20.
-            ).nth(0) {
+    if block_input.0 { return; }
+
+    if actions.just_pressed(Action::Press) {
+        if let Some(pos) = pointer.drag_ui {
+            if let Some((e, _)) = inventory.iter().find(|(_, c)| c.hovered) {
                 sfx.send(PlaySfxEvent(SFX::Clic));
 
                 // Spawn a tile
@@ -221,7 +318,7 @@ fn handle_click(
                             ..Default::default()
                         },
                         texture_atlas: textures.tile.clone(),
-                        transform: Transform::from_xyz(pos.x / 2. - 20., pos.y / 2. - 20. + 8., util::z::VEG_DRAG),
+                        transform: Transform::from_xyz(pos.x - 20., pos.y - 20. + 8., util::z::VEG_DRAG),
                         ..Default::default()
                     })
                     .id();
@@ -229,7 +326,8 @@ fn handle_click(
                 commands
                     .entity(tile_e)
                     .insert(EditorUI)
-                    .insert(DraggedTile(e.0.clone()));
+                    .insert(DraggedTile(e.0.clone()))
+                    .insert(Clickable::square(40.));
             }
         }
     }
@@ -237,27 +335,35 @@ fn handle_click(
 
 fn handle_drop(
     mut commands: Commands,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    actions: Res<Actions>,
+    pointer: Res<Pointer>,
     query: Query<(Entity, &DraggedTile, &Transform, Option<&PreviousPos>)>,
     mut puzzle: ResMut<CurrentPuzzle>,
     mut grid_changed: EventWriter<GridChanged>,
+    mut display_level: EventWriter<DisplayLevel>,
     mut sfx: EventWriter<PlaySfxEvent>,
+    ui_camera: Query<&Transform, (With<MainCamera>, Without<DraggedTile>)>,
+    garden_camera: Query<&Transform, (With<GardenCamera>, Without<DraggedTile>)>,
 ) {
     if puzzle.0.is_none() { return; }
     let puzzle = puzzle.0.as_mut().unwrap();
 
     let animation_len = 1000;
 
-    if mouse.just_released(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
+    if actions.just_pressed(Action::Release) {
+        if let Some(pos) = pointer.drag_garden {
+            let (ui_camera, garden_camera) = (ui_camera.single(), garden_camera.single());
+
             for (e, dragged, t, prev) in query.iter() {
                 commands.entity(e).remove::<DraggedTile>();
 
+                // Void tiles change the outline of the garden, channels and hex tiles have their own sprites
+                let redraw = dragged.0 == Tile::Void || dragged.0 == Tile::Channel || puzzle.is_hex();
+                if redraw { display_level.send(DisplayLevel); }
+
                 // Drop on a free tile of the grid -> animate to pos + update count
-                if let Some(tile) = crate::grid::get_pos_at(pos, puzzle.size) {
-                    let destination = if !puzzle.placed.contains_key(&tile) && !puzzle.tiles.contains_key(&tile) {
+                if let Some(tile) = crate::grid::get_pos_at(pos, puzzle) {
+                    let destination = if puzzle.occupant(tile).is_none() && !puzzle.tiles.contains_key(&tile) {
                         Some(tile)
                     } else if prev.is_some() {
                         Some(prev.unwrap().0)
@@ -269,14 +375,21 @@ fn handle_drop(
 
                         let tile = destination.unwrap();
                         puzzle.tiles.insert(tile, dragged.0.clone());
+                        if dragged.0 == Tile::Void { puzzle.trim_fences(); }
+
+                        if redraw {
+                            commands.entity(e).despawn_recursive();
+                            continue
+                        }
 
                         commands
                             .entity(e)
                             .remove::<PreviousPos>()
+                            .insert(garden_layer())
                             .insert(Animator::<Transform>::new(
                                 crate::tween::position_out(
-                                    t.translation.xy(),
-                                    crate::grid::get_tile_pos(tile, puzzle.size),
+                                    util::world_to_world(t.translation.xy(), ui_camera, garden_camera),
+                                    crate::grid::get_tile_pos(tile, puzzle),
                                     util::z::VEGGIE,
                                     animation_len / 2
                                 )
@@ -310,35 +423,88 @@ fn handle_drop(
 
 fn handle_click_on_grid(
     mut commands: Commands,
-    mut tiles: Query<(Entity, &GridTile, &mut Transform)>,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    mut tiles: Query<(Entity, &GridTile, &mut Transform, &Clickable)>,
+    actions: Res<Actions>,
     mut puzzle: ResMut<CurrentPuzzle>,
     mut grid_changed: EventWriter<GridChanged>,
+    fence_tool: Res<FenceTool>,
+    block_input: Res<BlockInput>,
 ) {
-    if puzzle.0.is_none() { return; }
+    if puzzle.0.is_none() || fence_tool.0 || block_input.0 { return; }
     let puzzle = puzzle.0.as_mut().unwrap();
 
-    if mouse.just_pressed(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
-            if let Some((e, grid_tile, mut t)) = tiles.iter_mut().filter(|(_, _, t)|
-                (t.translation.x + 20. - pos.x / 2.).abs() < 20.
-                    && (t.translation.y + 20. - pos.y / 2.).abs() < 20.
-            ).nth(0) {
-                commands
-                    .entity(e)
-                    .insert(DraggedTile(grid_tile.0.clone()))
-                    .insert(PreviousPos(grid_tile.1))
-                    .remove::<GridTile>();
-                puzzle.tiles.remove(&grid_tile.1);
-                t.translation.z = util::z::VEG_DRAG;
-                grid_changed.send(GridChanged);
+    if actions.just_pressed(Action::Press) {
+        if let Some((e, grid_tile, mut t, _)) = tiles.iter_mut().find(|(_, _, _, c)| c.hovered) {
+            commands
+                .entity(e)
+                .insert(DraggedTile(grid_tile.0.clone()))
+                .insert(PreviousPos(grid_tile.1))
+                .insert(RenderLayers::default())
+                .remove::<GridTile>();
+            puzzle.tiles.remove(&grid_tile.1);
+            t.translation.z = util::z::VEG_DRAG;
+            grid_changed.send(GridChanged);
+        }
+    }
+}
+
+/// With the fence tool, a click puts up or takes down the fence on the closest side of a tile
+fn handle_fence_click(
+    actions: Res<Actions>,
+    pointer: Res<Pointer>,
+    fence_tool: Res<FenceTool>,
+    block_input: Res<BlockInput>,
+    mut puzzle: ResMut<CurrentPuzzle>,
+    mut display_level: EventWriter<DisplayLevel>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+) {
+    if puzzle.0.is_none() || !fence_tool.0 || block_input.0 { return; }
+    let puzzle = puzzle.0.as_mut().unwrap();
+    if puzzle.is_hex() { return; }
+
+    if actions.just_pressed(Action::Press) {
+        if let Some(pos) = pointer.garden {
+            if let Some(tile) = get_pos_at(pos, puzzle) {
+                let local = pos - get_tile_pos(tile, puzzle);
+                let step = [((1, 0), 40. - local.x), ((-1, 0), local.x), ((0, 1), 40. - local.y), ((0, -1), local.y)]
+                    .into_iter()
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(step, _)| step)
+                    .unwrap();
+
+                // Fences only stand between two tiles of the garden
+                if !puzzle.is_garden(puzzle.wrapped((tile.0 + step.0, tile.1 + step.1))) { return; }
+
+                let edge = puzzle.edge(tile, step);
+                if !puzzle.fences.remove(&edge) { puzzle.fences.insert(edge); }
+                sfx.send(PlaySfxEvent(SFX::Place));
+                display_level.send(DisplayLevel);
             }
         }
     }
 }
 
+/// Right click on a placed veggie switches it between solution and given
+fn toggle_given(
+    veggies: Query<(&GridVeggie, &Clickable)>,
+    actions: Res<Actions>,
+    mut puzzle: ResMut<CurrentPuzzle>,
+    block_input: Res<BlockInput>,
+    mut display_level: EventWriter<DisplayLevel>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+) {
+    if puzzle.0.is_none() || block_input.0 { return; }
+    let puzzle = puzzle.0.as_mut().unwrap();
+
+    if actions.just_pressed(Action::Lock) {
+        if let Some((v, _)) = veggies.iter().find(|(_, c)| c.hovered) {
+            if !puzzle.givens.remove(&v.1) { puzzle.givens.insert(v.1); }
+            sfx.send(PlaySfxEvent(SFX::Clic));
+            display_level.send(DisplayLevel);
+        }
+    }
+}
+
 fn click_on_button(
     mut commands: Commands,
     mut clicks: EventReader<ButtonClick>,
@@ -346,8 +512,11 @@ fn click_on_button(
     mut display_level: EventWriter<DisplayLevel>,
     mut state: ResMut<State<GameState>>,
     mut sfx: EventWriter<PlaySfxEvent>,
+    mut fence_tool: ResMut<FenceTool>,
+    mut review: ResMut<Review>,
+    block_input: Res<BlockInput>,
 ) {
-    if current_puzzle.0.is_none() { return; }
+    if current_puzzle.0.is_none() || block_input.0 { return; }
     let mut puzzle = current_puzzle.0.as_mut().unwrap();
 
     for click in clicks.iter() {
@@ -363,20 +532,27 @@ fn click_on_button(
                 } else {
                     if rows {
                         puzzle.size.1 -= 1;
-                        // Remove placed veggies / tiles on the removed row
-                        (0..puzzle.size.0).for_each(|x| {
-                            puzzle.tiles.remove(&(x, puzzle.size.1));
-                            puzzle.placed.remove(&(x, puzzle.size.1));
-                        })
+                        // Remove tiles on the removed row
+                        (0..puzzle.size.0).for_each(|x| { puzzle.tiles.remove(&(x, puzzle.size.1)); })
                     } else {
                         puzzle.size.0 -= 1;
-                        // Remove placed veggies / tiles on the removed line
-                        (0..puzzle.size.1).for_each(|y| {
-                            puzzle.tiles.remove(&(puzzle.size.0, y));
-                            puzzle.placed.remove(&(puzzle.size.0, y));
-                        })
+                        // Remove tiles on the removed line
+                        (0..puzzle.size.1).for_each(|y| { puzzle.tiles.remove(&(puzzle.size.0, y)); })
+                    }
+
+                    // Remove veggies sticking out of the garden
+                    let size = puzzle.size;
+                    let outside = puzzle.placed
+                        .iter()
+                        .filter(|(p, v)| puzzle.footprint(v, **p).iter().any(|(x, y)| *x >= size.0 || *y >= size.1))
+                        .map(|(p, _)| *p)
+                        .collect::<Vec<_>>();
+                    for p in outside {
+                        puzzle.placed.remove(&p);
+                        puzzle.givens.remove(&p);
                     }
                 }
+                puzzle.trim_fences();
 
                 // Reposition stuff
                 display_level.send(DisplayLevel);
@@ -384,6 +560,8 @@ fn click_on_button(
             TextButtonId::Export => {
                 if let Some(text) = Encoder::encode_puzzle(&puzzle) {
                     data::write_level(text);
+                    review.0 = deduce::review(&solver::level(puzzle));
+                    display_level.send(DisplayLevel);
                 } else {
                     sfx.send(PlaySfxEvent(SFX::Error));
                 }
@@ -398,6 +576,66 @@ fn click_on_button(
                 }
             }
 
+            TextButtonId::Adjacency => {
+                puzzle.adjacency = puzzle.adjacency.next();
+                if puzzle.is_hex() {
+                    puzzle.wrap = false;
+                    puzzle.fences.clear();
+                }
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Wrap => {
+                if puzzle.is_hex() { continue; }
+                puzzle.wrap = !puzzle.wrap;
+                puzzle.trim_fences();
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::FenceTool => {
+                fence_tool.0 = !fence_tool.0;
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Seasons => {
+                puzzle.seasons = (puzzle.seasons + 1) % (season::MAX_SEASONS + 1);
+                if puzzle.seasons > 0 {
+                    puzzle.scored = false;
+                    puzzle.sad = 0;
+                }
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Scored => {
+                puzzle.scored = !puzzle.scored;
+                if puzzle.scored {
+                    puzzle.seasons = 0;
+                    puzzle.sad = 0;
+                }
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Tolerance => {
+                // At most 1 to 3 sad veggies, then exactly 1 to 3
+                let options = [(0, false), (1, false), (2, false), (3, false), (1, true), (2, true), (3, true)];
+                let i = options.iter().position(|o| *o == (puzzle.sad, puzzle.exact_sad)).map_or(0, |i| i + 1);
+                (puzzle.sad, puzzle.exact_sad) = options[i % options.len()];
+                if puzzle.sad > 0 {
+                    puzzle.seasons = 0;
+                    puzzle.scored = false;
+                }
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Moves => {
+                // No limit, then the moves of the author's garden with a little leeway
+                let par = puzzle.placed.keys().filter(|p| !puzzle.givens.contains(*p)).count().max(1);
+                let options = [0, par, par + 2, par + 5];
+                let i = options.iter().position(|m| *m == puzzle.moves).map_or(0, |i| i + 1);
+                puzzle.moves = options[i % options.len()];
+                display_level.send(DisplayLevel);
+            }
+
             TextButtonId::Clear => {
                 commands.insert_resource(CurrentPuzzle(Some(Puzzle::default())));
                 display_level.send(DisplayLevel);
@@ -416,9 +654,15 @@ fn update_author(
     keyboard_input: Res<Input<KeyCode>>,
     mut puzzle: ResMut<CurrentPuzzle>,
     mut refresh: EventWriter<DisplayLevel>,
+    held: Query<(), With<Held>>,
+    block_input: Res<BlockInput>,
 ) {
+    if block_input.0 { return; }
+
     for code in keyboard_input.get_just_pressed() {
         match get_char(code) {
+            // Backspace gives a held veggie back to the inventory first
+            Some('<') if !held.is_empty() => { return; },
             Some('<') => { puzzle.0.as_mut().unwrap().author.pop(); },
             Some(c) => {
                 let p = puzzle.0.as_mut().unwrap();
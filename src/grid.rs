@@ -1,11 +1,20 @@
+use bevy::input::mouse::MouseWheel;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
 use bevy::sprite::Anchor;
+use bevy::utils::HashSet;
 
-use crate::{BlockInput, GameState, HEIGHT, puzzle, util, WIDTH};
-use crate::inventory::DraggedVeg;
+use crate::{BlockInput, GameState, GardenCamera, HEIGHT, puzzle, util, WIDTH};
+use crate::audio::{Harmony, PlaySfxEvent, SFX};
+use crate::editor::FenceTool;
+use crate::input::{Action, Actions, Clickable, Pointer};
+use crate::inventory::{DraggedVeg, Held};
 use crate::loading::Textures;
-use crate::puzzle::{Puzzle, Tile};
-use crate::veggie::{Expression, spawn_veggie, UpdateFaces, Veggie};
+use crate::play::Moves;
+use crate::puzzle::{Puzzle, Side, Tile};
+use crate::text::ButtonFocus;
+use crate::util::Colors;
+use crate::veggie::{add_lock, Expression, spawn_veggie, UpdateFaces, Veggie};
 
 pub struct GridPlugin;
 
@@ -13,9 +22,12 @@ impl Plugin for GridPlugin {
     fn build(&self, app: &mut App) {
         app
             .insert_resource(CurrentPuzzle(None))
+            .init_resource::<GardenView>()
+            .init_resource::<Cursor>()
             .add_event::<DisplayLevel>()
             .add_event::<DestroyLevel>()
-            .add_event::<GridChanged>();
+            .add_event::<GridChanged>()
+            .add_system(propagate_layers);
 
 
         for state in [GameState::Play, GameState::Editor] {
@@ -23,8 +35,17 @@ impl Plugin for GridPlugin {
                 .add_system_set(SystemSet::on_enter(state).with_system(setup))
                 .add_system_set(SystemSet::on_update(state)
                     .with_system(update.before("logic"))
+                    .with_system(update_harmony.after(update))
                     .with_system(display_level.label("logic"))
+                    .with_system(display_ghosts)
+                    .with_system(display_shade)
+                    .with_system(update_channels)
+                    .with_system(animate_channels)
                     .with_system(handle_click.label("logic"))
+                    .with_system(move_cursor.before("logic"))
+                    .with_system(display_cursor)
+                    .with_system(fit_camera)
+                    .with_system(pan_zoom)
                 )
                 .add_system_set(SystemSet::on_exit(state).with_system(cleanup))
             ;
@@ -35,9 +56,42 @@ impl Plugin for GridPlugin {
 #[derive(Component)]
 pub struct GridUI;
 
+/// Render layer of the garden, seen through the garden camera
+pub const GARDEN_LAYER: u8 = 1;
+
+/// Hex sprites are taller than the square the veggies stand on
+const HEX_OFFSET: Vec2 = Vec2::new(-4., -9.);
+
+/// Part of the screen the garden is fitted in when displayed
+const GARDEN_AREA: Vec2 = Vec2::new(420., 300.);
+
+pub fn garden_layer() -> RenderLayers {
+    RenderLayers::layer(GARDEN_LAYER)
+}
+
+/// Pan and zoom of the garden camera
+#[derive(Resource, Default)]
+struct GardenView {
+    /// Size and wrapping of the garden the camera was last fitted to
+    fitted: Option<((i8, i8), bool)>,
+    /// Point of the garden grabbed while panning
+    drag: Option<Vec2>,
+}
+
 #[derive(Resource)]
 pub struct CurrentPuzzle(pub Option<Puzzle>);
 
+/// Tile selected with the arrow keys
+#[derive(Resource, Default)]
+pub struct Cursor {
+    pub pos: (i8, i8),
+    /// Shown once the arrow keys are used, hidden again by a click
+    pub visible: bool,
+}
+
+#[derive(Component)]
+struct CursorUI;
+
 pub struct DisplayLevel;
 struct DestroyLevel;
 
@@ -46,10 +100,29 @@ pub struct GridChanged;
 #[derive(Component)]
 pub struct GridVeggie(pub Veggie, pub (i8, i8), pub (bool, bool));
 
+/// Given veggie, locked on the grid during play
+#[derive(Component)]
+pub struct Locked;
+
+/// Faded copy of an edge tile, drawn across the border of a wrapping garden
+#[derive(Component)]
+struct Ghost;
+
+/// Darkened tile in the shadow of a sunflower
+#[derive(Component)]
+struct Shade;
+
+/// Channel drawn with the sides it connects to, in square gardens
+#[derive(Component)]
+struct Flow;
+
 fn setup(
     mut display_level: EventWriter<DisplayLevel>,
-    mut state: ResMut<State<GameState>>,
+    mut view: ResMut<GardenView>,
+    mut cursor: ResMut<Cursor>,
 ) {
+    view.fitted = None;
+    *cursor = Cursor::default();
     display_level.send(DisplayLevel);
 }
 
@@ -63,48 +136,78 @@ fn display_level(
     puzzle: Res<CurrentPuzzle>,
     entities: Query<Entity, With<GridUI>>,
     mut grid_changed: EventWriter<GridChanged>,
+    state: Res<State<GameState>>,
 ) {
+    let in_editor = state.current() == &GameState::Editor;
+
     for _ in ev.iter() {
         // "Reset grid"
         entities.iter().for_each(|e| commands.entity(e).despawn_recursive());
 
         if let Some(puzzle) = &puzzle.0 {
-            let h = (HEIGHT - puzzle.size.1 as f32 * 40.) / 2.;
-            let w = (WIDTH - puzzle.size.0 as f32 * 40.) / 2.;
+            let hex = puzzle.is_hex();
+            let wet = puzzle.wet_channels();
 
             // Tiles
             for y in 0..puzzle.size.1 {
                 for x in 0..puzzle.size.0 {
-                    let tile_x = w + x as f32 * 40.;
-                    let tile_y = h + y as f32 * 40.;
-
-                    commands
-                        .spawn(SpriteSheetBundle {
-                            sprite: TextureAtlasSprite {
-                                index: ((x + y) % 2) as usize,
-                                anchor: Anchor::BottomLeft,
-                                ..Default::default()
-                            },
-                            transform: Transform::from_xyz(tile_x, tile_y, util::z::TILE),
-                            texture_atlas: textures.tile.clone(),
-                            ..Default::default()
-                        })
-                        .insert(GridUI);
+                    let Vec2 { x: tile_x, y: tile_y } = get_tile_pos((x, y), puzzle);
+
+                    if puzzle.is_garden((x, y)) {
+                        let mut ground = vec![(((x + y) % 2) as usize, util::z::TILE)];
+                        // Hex borders are the outlines of larger hexes below the garden
+                        if hex { ground.push((2, util::z::TILE_BELOW)); }
+
+                        for (index, z) in ground {
+                            let (texture_atlas, offset) = if hex {
+                                (textures.hex.clone(), HEX_OFFSET)
+                            } else {
+                                (textures.tile.clone(), Vec2::ZERO)
+                            };
+                            commands
+                                .spawn(SpriteSheetBundle {
+                                    sprite: TextureAtlasSprite {
+                                        index,
+                                        anchor: Anchor::BottomLeft,
+                                        ..Default::default()
+                                    },
+                                    transform: Transform::from_xyz(tile_x + offset.x, tile_y + offset.y, z),
+                                    texture_atlas,
+                                    ..Default::default()
+                                })
+                                .insert(GridUI)
+                                .insert(garden_layer());
+                        }
+                    }
 
                     if let Some(tile) = puzzle.tiles.get(&(x, y)) {
-                        commands
+                        let flow = !hex && tile == &Tile::Channel;
+                        let (texture_atlas, index, offset) = if hex && tile == &Tile::Water {
+                            (textures.hex.clone(), 3, HEX_OFFSET)
+                        } else if flow {
+                            (textures.channel.clone(), channel_index((x, y), puzzle, &wet), Vec2::ZERO)
+                        } else {
+                            (textures.tile.clone(), tile.index(), Vec2::ZERO)
+                        };
+                        let id = commands
                             .spawn(SpriteSheetBundle {
                                 sprite: TextureAtlasSprite {
-                                    index: tile.index(),
+                                    index,
                                     anchor: Anchor::BottomLeft,
                                     ..Default::default()
                                 },
-                                transform: Transform::from_xyz(tile_x, tile_y, util::z::TILE_ABOVE),
-                                texture_atlas: textures.tile.clone(),
+                                transform: Transform::from_xyz(tile_x + offset.x, tile_y + offset.y, util::z::TILE_ABOVE),
+                                texture_atlas,
+                                // Void tiles are only shown in the editor
+                                visibility: Visibility { is_visible: in_editor || tile != &Tile::Void },
                                 ..Default::default()
                             })
                             .insert(GridTile(tile.clone(), (x, y)))
-                            .insert(GridUI);
+                            .insert(Clickable::square(40.))
+                            .insert(GridUI)
+                            .insert(garden_layer())
+                            .id();
+                        if flow { commands.entity(id).insert(Flow); }
                     }
 
                     if let Some(veggie) = puzzle.placed.get(&(x, y)) {
@@ -118,137 +221,533 @@ fn display_level(
                         commands
                             .entity(id)
                             .insert(GridVeggie(veggie.clone(), (x, y), (false, false)))
-                            .insert(GridUI);
+                            .insert(GridUI)
+                            .insert(garden_layer());
+
+                        if puzzle.givens.contains(&(x, y)) {
+                            commands.entity(id).insert(Locked);
+                            add_lock(&mut commands, &textures, id);
+                        }
                     }
                 }
             }
 
-            // Corners
-            for (dx, dy, fx, fy, dx2, dy2, sx, sy, i) in [
-                (-8., puzzle.size.1 as f32 * 40., false, false, 8., 0., puzzle.size.0 as f32 * 5., 1., 1),
-                (puzzle.size.0 as f32 * 40., puzzle.size.1 as f32 * 40., true, false, 0., -1. * puzzle.size.1 as f32 * 40., 1., puzzle.size.1 as f32 * 5., 2),
-                (-8., -8., false, true, 8., 0., puzzle.size.0 as f32 * 5., 1., 3),
-                (puzzle.size.0 as f32 * 40., -8., true, true, -1. * puzzle.size.0 as f32 * 40. - 8., 8., 1., puzzle.size.1 as f32 * 5., 4),
-            ] {
+            // Fences, turned sideways on the north side of the tiles
+            for &(pos, side) in puzzle.fences.iter() {
+                let tile = get_tile_pos(pos, puzzle);
+                let (offset, angle) = match side {
+                    Side::East => (Vec2::new(40., 20.), 0.),
+                    Side::North => (Vec2::new(20., 40.), std::f32::consts::FRAC_PI_2),
+                };
                 commands
-                    .spawn(SpriteSheetBundle {
-                        sprite: TextureAtlasSprite {
-                            index: 0,
-                            flip_x: fx,
-                            flip_y: fy,
-                            anchor: Anchor::BottomLeft,
-                            ..Default::default()
-                        },
-                        transform: Transform::from_xyz(w + dx, h + dy, util::z::TILE),
-                        texture_atlas: textures.border.clone(),
+                    .spawn(SpriteBundle {
+                        texture: textures.fence.clone(),
+                        transform: Transform::from_xyz(tile.x + offset.x, tile.y + offset.y, util::z::FENCE)
+                            .with_rotation(Quat::from_rotation_z(angle)),
                         ..Default::default()
                     })
-                    .insert(GridUI);
+                    .insert(GridUI)
+                    .insert(garden_layer());
+            }
+
+            // Border following the outline of the garden
+            for y in 0..puzzle.size.1 {
+                for x in 0..puzzle.size.0 {
+                    if hex || !puzzle.is_garden((x, y)) { continue; }
+                    let Vec2 { x: tile_x, y: tile_y } = get_tile_pos((x, y), puzzle);
+                    let top = !puzzle.is_garden((x, y + 1));
+                    let bottom = !puzzle.is_garden((x, y - 1));
+                    let left = !puzzle.is_garden((x - 1, y));
+                    let right = !puzzle.is_garden((x + 1, y));
+
+                    // Sides
+                    for (border, dx, dy, sx, sy, i) in [
+                        (top, 0., 40., 5., 1., 1),
+                        (right, 40., 0., 1., 5., 2),
+                        (bottom, 0., -8., 5., 1., 3),
+                        (left, -8., 0., 1., 5., 4),
+                    ] {
+                        if !border { continue; }
+                        commands
+                            .spawn(SpriteSheetBundle {
+                                sprite: TextureAtlasSprite {
+                                    index: i,
+                                    anchor: Anchor::BottomLeft,
+                                    ..Default::default()
+                                },
+                                transform: Transform {
+                                    translation: Vec3::new(tile_x + dx, tile_y + dy, util::z::TILE),
+                                    scale: Vec3::new(sx, sy, 1.),
+                                    ..Default::default()
+                                },
+                                texture_atlas: textures.border.clone(),
+                                ..Default::default()
+                            })
+                            .insert(GridUI)
+                            .insert(garden_layer());
+                    }
 
+                    // Corners
+                    for (corner, dx, dy, fx, fy) in [
+                        (top && left, -8., 40., false, false),
+                        (top && right, 40., 40., true, false),
+                        (bottom && left, -8., -8., false, true),
+                        (bottom && right, 40., -8., true, true),
+                    ] {
+                        if !corner { continue; }
+                        commands
+                            .spawn(SpriteSheetBundle {
+                                sprite: TextureAtlasSprite {
+                                    index: 0,
+                                    flip_x: fx,
+                                    flip_y: fy,
+                                    anchor: Anchor::BottomLeft,
+                                    ..Default::default()
+                                },
+                                transform: Transform::from_xyz(tile_x + dx, tile_y + dy, util::z::TILE),
+                                texture_atlas: textures.border.clone(),
+                                ..Default::default()
+                            })
+                            .insert(GridUI)
+                            .insert(garden_layer());
+                    }
+                }
+            }
+
+            grid_changed.send(GridChanged);
+        }
+    }
+}
+
+fn display_ghosts(
+    mut commands: Commands,
+    textures: Res<Textures>,
+    mut changed: EventReader<GridChanged>,
+    puzzle: Res<CurrentPuzzle>,
+    ghosts: Query<Entity, With<Ghost>>,
+) {
+    if changed.iter().count() == 0 || puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+
+    ghosts.iter().for_each(|e| commands.entity(e).despawn_recursive());
+    if !puzzle.wrap { return; }
+
+    let faded = Color::rgba(1., 1., 1., 0.35);
+    let (w, h) = puzzle.size;
+    for y in -1..=h {
+        for x in -1..=w {
+            if x >= 0 && x < w && y >= 0 && y < h { continue; }
+            let source = (x.rem_euclid(w), y.rem_euclid(h));
+            if !puzzle.is_garden(source) { continue; }
+
+            // Outside of the border
+            let side = |n: i8, max: i8| if n < 0 { -8. } else if n >= max { 8. } else { 0. };
+            let pos = get_tile_pos((x, y), puzzle) + Vec2::new(side(x, w), side(y, h));
+
+            let mut sprites = vec![(textures.tile.clone(), ((source.0 + source.1) % 2) as usize, util::z::TILE)];
+            if let Some(tile) = puzzle.tiles.get(&source) {
+                sprites.push((textures.tile.clone(), tile.index(), util::z::TILE_ABOVE));
+            }
+            // Big veggies would spill over the garden
+            if let Some(veggie) = puzzle.placed.get(&source).filter(|v| v.span() == 1) {
+                sprites.push((textures.fruit.clone(), veggie.sprite(), util::z::VEGGIE));
+            }
+
+            for (texture_atlas, index, z) in sprites {
                 commands
                     .spawn(SpriteSheetBundle {
                         sprite: TextureAtlasSprite {
-                            index: i,
+                            index,
+                            color: faded,
                             anchor: Anchor::BottomLeft,
                             ..Default::default()
                         },
-                        transform: Transform {
-                            translation: Vec3::new(w + dx + dx2, h + dy + dy2, util::z::TILE),
-                            scale: Vec3::new(sx, sy, 1.),
-                            ..Default::default()
-                        },
-                        texture_atlas: textures.border.clone(),
+                        transform: Transform::from_xyz(pos.x, pos.y, z),
+                        texture_atlas,
                         ..Default::default()
                     })
-                    .insert(GridUI);
+                    .insert(Ghost)
+                    .insert(garden_layer());
             }
-
-            grid_changed.send(GridChanged);
         }
     }
 }
 
-pub fn get_pos_at(cursor_pos: Vec2, puzzle_size: (i8, i8)) -> Option<(i8, i8)> {
-    let (x, y) = (cursor_pos.x / 2., cursor_pos.y / 2.);
+/// Darken the tiles in the shadow of the sunflowers
+fn display_shade(
+    mut commands: Commands,
+    textures: Res<Textures>,
+    mut changed: EventReader<GridChanged>,
+    puzzle: Res<CurrentPuzzle>,
+    shades: Query<Entity, With<Shade>>,
+) {
+    if changed.iter().count() == 0 || puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+
+    shades.iter().for_each(|e| commands.entity(e).despawn_recursive());
+
+    for tile in puzzle.shade() {
+        let pos = get_tile_pos(tile, puzzle);
+        // Tinted ground sprite, so hex tiles get a hex shadow
+        let (texture_atlas, offset) = if puzzle.is_hex() {
+            (textures.hex.clone(), HEX_OFFSET)
+        } else {
+            (textures.tile.clone(), Vec2::ZERO)
+        };
+        commands
+            .spawn(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index: 0,
+                    color: Color::rgba(0., 0., 0., 0.3),
+                    anchor: Anchor::BottomLeft,
+                    ..Default::default()
+                },
+                transform: Transform::from_xyz(pos.x + offset.x, pos.y + offset.y, util::z::SHADE),
+                texture_atlas,
+                ..Default::default()
+            })
+            .insert(Shade)
+            .insert(garden_layer());
+    }
+}
 
-    let h = (HEIGHT - puzzle_size.1 as f32 * 40.) / 2.;
-    let w = (WIDTH - puzzle_size.0 as f32 * 40.) / 2.;
+/// Frame of a channel: the sides it connects to (east 1, north 2, west 4, south 8),
+/// on the wet rows when water flows in it
+fn channel_index(pos: (i8, i8), puzzle: &Puzzle, wet: &HashSet<(i8, i8)>) -> usize {
+    let mask = [(1, 0), (0, 1), (-1, 0), (0, -1)]
+        .iter()
+        .enumerate()
+        .filter(|(_, (dx, dy))| matches!(
+            puzzle.tiles.get(&puzzle.wrapped((pos.0 + dx, pos.1 + dy))),
+            Some(Tile::Channel) | Some(Tile::Source)
+        ))
+        .map(|(i, _)| 1 << i)
+        .sum::<usize>();
+    mask + if wet.contains(&pos) { 16 } else { 0 }
+}
+
+fn update_channels(
+    mut changed: EventReader<GridChanged>,
+    puzzle: Res<CurrentPuzzle>,
+    mut channels: Query<(&GridTile, &mut TextureAtlasSprite), With<Flow>>,
+) {
+    if changed.iter().count() == 0 || puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+
+    let wet = puzzle.wet_channels();
+    for (tile, mut sprite) in channels.iter_mut() {
+        sprite.index = channel_index(tile.1, puzzle, &wet);
+    }
+}
+
+/// Water flows in the wet channels, alternating between two frames
+fn animate_channels(
+    time: Res<Time>,
+    mut channels: Query<&mut TextureAtlasSprite, With<Flow>>,
+) {
+    let frame = (time.elapsed_seconds() * 3.) as usize % 2;
+    for mut sprite in channels.iter_mut() {
+        if sprite.index >= 16 { sprite.index = sprite.index % 16 + 16 * (1 + frame); }
+    }
+}
+
+/// Size of the garden in pixels, borders excluded
+fn garden_size(puzzle: &Puzzle) -> Vec2 {
+    // Shifted rows stick out half a tile
+    let shift = if puzzle.is_hex() && puzzle.size.1 > 1 { 20. } else { 0. };
+    Vec2::new(puzzle.size.0 as f32 * 40. + shift, puzzle.size.1 as f32 * 40.)
+}
+
+/// Tile at a position of the garden world
+pub fn get_pos_at(world_pos: Vec2, puzzle: &Puzzle) -> Option<(i8, i8)> {
+    let (x, y) = (world_pos.x, world_pos.y);
+    let size = garden_size(puzzle);
+
+    let h = (HEIGHT - size.y) / 2.;
+    let w = (WIDTH - size.x) / 2.;
 
     let t_x = (x - w) / 40.;
     let t_y = (y - h) / 40.;
 
-    if t_x > 0. && t_x < puzzle_size.0 as f32 && t_y > 0. && t_y < puzzle_size.1 as f32 {
+    if puzzle.is_hex() {
+        // Closest hex center among the tiles around
+        let (t_x, t_y) = (t_x.floor() as i8, t_y.floor() as i8);
+        return (t_y - 1..=t_y + 1)
+            .flat_map(|y| (t_x - 1..=t_x + 1).map(move |x| (x, y)))
+            .filter(|&(x, y)| x >= 0 && x < puzzle.size.0 && y >= 0 && y < puzzle.size.1)
+            .map(|pos| (pos, world_pos.distance(get_tile_pos(pos, puzzle) + 20.)))
+            .filter(|(_, d)| *d < 25.)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(pos, _)| pos);
+    }
+
+    if t_x > 0. && t_x < puzzle.size.0 as f32 && t_y > 0. && t_y < puzzle.size.1 as f32 {
         return Some((t_x as i8, t_y as i8));
     }
     return None;
 }
 
-pub fn get_tile_pos(tile: (i8, i8), puzzle_size: (i8, i8)) -> Vec2 {
-    let h = (HEIGHT - puzzle_size.1 as f32 * 40.) / 2.;
-    let w = (WIDTH - puzzle_size.0 as f32 * 40.) / 2.;
+/// Bottom left corner of the square a veggie stands on
+pub fn get_tile_pos(tile: (i8, i8), puzzle: &Puzzle) -> Vec2 {
+    let size = garden_size(puzzle);
+    let h = (HEIGHT - size.y) / 2.;
+    let w = (WIDTH - size.x) / 2.;
 
-    return Vec2::new(w + tile.0 as f32 * 40., h + tile.1 as f32 * 40.);
+    let shift = if puzzle.is_hex() && tile.1 & 1 == 1 { 20. } else { 0. };
+    return Vec2::new(w + tile.0 as f32 * 40. + shift, h + tile.1 as f32 * 40.);
 }
 
 fn update(
     mut changed: EventReader<GridChanged>,
-    mut veggies: Query<(&mut GridVeggie, Entity)>,
+    mut veggies: Query<(&mut GridVeggie, Entity, ChangeTrackers<GridVeggie>)>,
     mut update_faces: EventWriter<UpdateFaces>,
     puzzle: Res<CurrentPuzzle>,
+    mut sfx: EventWriter<PlaySfxEvent>,
 ) {
     for _ in changed.iter() {
         if puzzle.0.is_none() { return; }
         let puzzle = puzzle.0.as_ref().unwrap();
 
-        for (mut veg, e) in veggies.iter_mut() {
-            let state = puzzle::is_happy(&veg.0, veg.1, &puzzle.tiles, &puzzle.placed);
+        let (mut became_sad, mut became_happy) = (false, false);
+        for (mut veg, e, tracker) in veggies.iter_mut() {
+            let state = puzzle::is_happy(&veg.0, veg.1, puzzle);
+            // Veggies just planted or displayed have no previous mood
+            if !tracker.is_added() {
+                let (was, is) = (veg.2.0 && veg.2.1, state.0 && state.1);
+                became_sad |= was && !is;
+                became_happy |= !was && is;
+            }
             veg.2 = state;
             let exp = |b| if b { Expression::Happy } else { Expression::Sad };
             update_faces.send(UpdateFaces(e, (exp(state.0), exp(state.1))));
         }
+
+        if became_sad { sfx.send(PlaySfxEvent(SFX::BecameSad)); }
+        if became_happy { sfx.send(PlaySfxEvent(SFX::BecameHappy)); }
     }
 }
 
+/// Share of the veggies of the level that are planted and happy
+fn update_harmony(
+    mut changed: EventReader<GridChanged>,
+    veggies: Query<&GridVeggie>,
+    puzzle: Res<CurrentPuzzle>,
+    mut harmony: ResMut<Harmony>,
+) {
+    if changed.iter().count() == 0 || puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+
+    // Veggies still in the inventory count as not happy yet
+    let unplanted = puzzle.veggies.iter()
+        .map(|(veggie, n)| (*n as usize).saturating_sub(
+            puzzle.placed.iter().filter(|(p, v)| *v == veggie && !puzzle.givens.contains(*p)).count()
+        ))
+        .sum::<usize>();
+    let happy = veggies.iter().filter(|v| v.2.0 && v.2.1).count();
+    let total = veggies.iter().count() + unplanted;
+
+    harmony.0 = if total == 0 { 0. } else { happy as f32 / total as f32 };
+}
+
 #[derive(Component)]
 pub struct PreviousPos(pub (i8, i8));
 
 fn handle_click(
     mut commands: Commands,
-    mut veggies: Query<(Entity, &GridVeggie, &mut Transform)>,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    mut veggies: Query<(Entity, &GridVeggie, &mut Transform, &Clickable, Option<&Locked>)>,
+    actions: Res<Actions>,
     mut puzzle: ResMut<CurrentPuzzle>,
     mut update_faces: EventWriter<UpdateFaces>,
     mut grid_changed: EventWriter<GridChanged>,
     block_input: Res<BlockInput>,
+    state: Res<State<GameState>>,
+    fence_tool: Res<FenceTool>,
+    mut moves: ResMut<Moves>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+    cursor: Res<Cursor>,
+    focus: Res<ButtonFocus>,
+    held: Query<(), With<Held>>,
 ) {
-    if puzzle.0.is_none() || block_input.0 { return; }
+    let in_editor = state.current() == &GameState::Editor;
+    if puzzle.0.is_none() || block_input.0 || in_editor && fence_tool.0 { return; }
     let puzzle = puzzle.0.as_mut().unwrap();
 
-    if mouse.just_pressed(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
-            if let Some((e, v, mut t)) = veggies.iter_mut().filter(|(_, _, t)|
-                (t.translation.x + 20. - pos.x / 2.).abs() < 20.
-                    && (t.translation.y + 20. - pos.y / 2.).abs() < 20.
-            ).nth(0) {
-                commands
-                    .entity(e)
-                    .insert(DraggedVeg(v.0))
-                    .insert(PreviousPos(v.1))
-                    .remove::<GridVeggie>();
-                puzzle.placed.remove(&v.1);
-                t.translation.z = util::z::VEG_DRAG;
-                grid_changed.send(GridChanged);
-                update_faces.send(UpdateFaces(e, (Expression::Surprised, Expression::Surprised)));
+    // Confirm picks up the veggie under the grid cursor
+    let key = cursor.visible && focus.0.is_none() && held.is_empty() && actions.just_pressed(Action::Confirm);
+
+    if actions.just_pressed(Action::Press) || key {
+        let occupant = puzzle.occupant(cursor.pos).map(|(p, _)| p);
+        if let Some((e, v, mut t, _, _)) = veggies.iter_mut().filter(|(_, v, _, clickable, locked)| {
+            let picked = if key { occupant == Some(v.1) } else { clickable.hovered };
+            picked && (in_editor || locked.is_none())
+        }).nth(0) {
+            // Moving a veggie around the garden costs a move
+            if !in_editor {
+                if puzzle.out_of_moves(moves.count) {
+                    sfx.send(PlaySfxEvent(SFX::Error));
+                    return;
+                }
+                moves.count += 1;
             }
+            sfx.send(PlaySfxEvent(SFX::PickUp(v.0)));
+
+            // Dragged veggies follow the cursor on the UI layer
+            commands
+                .entity(e)
+                .insert(DraggedVeg(v.0))
+                .insert(PreviousPos(v.1))
+                .insert(RenderLayers::default())
+                .remove::<GridVeggie>();
+            if key { commands.entity(e).insert(Held); }
+            puzzle.placed.remove(&v.1);
+            puzzle.givens.remove(&v.1);
+            t.translation.z = util::z::VEG_DRAG;
+            grid_changed.send(GridChanged);
+            update_faces.send(UpdateFaces(e, (Expression::Surprised, Expression::Surprised)));
+        }
+    }
+}
+
+/// Arrows move the grid cursor, wrapping around in gardens that wrap
+fn move_cursor(
+    actions: Res<Actions>,
+    puzzle: Res<CurrentPuzzle>,
+    mut cursor: ResMut<Cursor>,
+) {
+    if puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+
+    if actions.just_pressed(Action::Press) { cursor.visible = false; }
+
+    let mut delta = (0, 0);
+    for (action, d) in [(Action::Left, (-1, 0)), (Action::Right, (1, 0)), (Action::Down, (0, -1)), (Action::Up, (0, 1))] {
+        if actions.just_pressed(action) { delta = d; }
+    }
+    if delta == (0, 0) && cursor.visible {
+        // The garden may have shrunk in the editor
+        if cursor.pos.0 >= puzzle.size.0 || cursor.pos.1 >= puzzle.size.1 {
+            cursor.pos = (cursor.pos.0.min(puzzle.size.0 - 1), cursor.pos.1.min(puzzle.size.1 - 1));
+        }
+        return;
+    }
+    if delta == (0, 0) { return; }
+
+    // The first key press only shows the cursor
+    if !cursor.visible {
+        cursor.visible = true;
+        return;
+    }
+
+    let (x, y) = (cursor.pos.0 + delta.0, cursor.pos.1 + delta.1);
+    cursor.pos = if puzzle.wrap {
+        (x.rem_euclid(puzzle.size.0), y.rem_euclid(puzzle.size.1))
+    } else {
+        (x.clamp(0, puzzle.size.0 - 1), y.clamp(0, puzzle.size.1 - 1))
+    };
+}
+
+fn display_cursor(
+    mut commands: Commands,
+    cursor: Res<Cursor>,
+    puzzle: Res<CurrentPuzzle>,
+    mut query: Query<(&mut Transform, &mut Visibility), With<CursorUI>>,
+) {
+    if puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+    let pos = get_tile_pos(cursor.pos, puzzle).extend(util::z::CURSOR);
+
+    if query.is_empty() {
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: *Colors::Beige.get().set_a(0.4),
+                    custom_size: Some(Vec2::splat(40.)),
+                    anchor: Anchor::BottomLeft,
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(pos),
+                visibility: Visibility { is_visible: cursor.visible },
+                ..Default::default()
+            })
+            .insert(CursorUI)
+            .insert(GridUI)
+            .insert(garden_layer());
+        return;
+    }
+
+    for (mut t, mut visibility) in query.iter_mut() {
+        t.translation = pos;
+        visibility.is_visible = cursor.visible;
+    }
+}
+
+/// Zoom the garden camera out until the whole garden fits on screen
+fn fit_camera(
+    mut ev: EventReader<DisplayLevel>,
+    puzzle: Res<CurrentPuzzle>,
+    mut view: ResMut<GardenView>,
+    mut camera: Query<&mut Transform, With<GardenCamera>>,
+) {
+    if ev.iter().count() == 0 || puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+    if view.fitted == Some((puzzle.size, puzzle.wrap)) { return; }
+    view.fitted = Some((puzzle.size, puzzle.wrap));
+
+    // Border and ghost tiles included
+    let size = garden_size(puzzle) + if puzzle.wrap { 96. } else { 16. };
+    let scale = 0.5 * (size / GARDEN_AREA).max_element().max(1.);
+
+    let mut t = camera.single_mut();
+    t.translation.x = WIDTH / 2.;
+    t.translation.y = HEIGHT / 2.;
+    t.scale = Vec3::new(scale, scale, 1.);
+}
+
+/// Wheel to zoom around the cursor, middle button to pan
+fn pan_zoom(
+    mut wheel: EventReader<MouseWheel>,
+    actions: Res<Actions>,
+    pointer: Res<Pointer>,
+    mut view: ResMut<GardenView>,
+    mut camera: Query<&mut Transform, With<GardenCamera>>,
+) {
+    if pointer.garden.is_none() { return; }
+    let anchor = pointer.garden.unwrap();
+    let mut t = camera.single_mut();
+
+    for ev in wheel.iter() {
+        let scale = (t.scale.x * 1.1_f32.powf(-ev.y.signum())).clamp(0.25, 4.);
+        let offset = (t.translation.truncate() - anchor) * scale / t.scale.x;
+        t.translation.x = anchor.x + offset.x;
+        t.translation.y = anchor.y + offset.y;
+        t.scale = Vec3::new(scale, scale, 1.);
+    }
+
+    // The point of the garden grabbed stays under the pointer
+    if actions.pressed(Action::Pan) {
+        let grabbed = *view.drag.get_or_insert(anchor);
+        t.translation.x -= anchor.x - grabbed.x;
+        t.translation.y -= anchor.y - grabbed.y;
+    } else {
+        view.drag = None;
+    }
+}
+
+/// Children are drawn on the same render layer as their parent
+fn propagate_layers(
+    mut commands: Commands,
+    query: Query<(&RenderLayers, &Children), Or<(Changed<RenderLayers>, Changed<Children>)>>,
+) {
+    for (layers, children) in query.iter() {
+        for child in children {
+            commands.entity(*child).insert(*layers);
         }
     }
 }
 
 fn cleanup(
     mut commands: Commands,
-    query: Query<Entity, With<GridUI>>,
+    query: Query<Entity, Or<(With<GridUI>, With<Ghost>, With<Shade>)>>,
 ) {
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
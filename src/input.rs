@@ -0,0 +1,372 @@
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+use bevy::utils::HashSet;
+
+use crate::{GardenCamera, MainCamera, util};
+use crate::grid::GARDEN_LAYER;
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Actions>()
+            .init_resource::<Pointer>()
+            .add_system_to_stage(CoreStage::PreUpdate, read_actions.after(InputSystem))
+            .add_system_to_stage(CoreStage::PreUpdate, read_pointer.after(read_actions))
+            .add_system_to_stage(CoreStage::PreUpdate, locate_pointer.after(read_pointer))
+            .add_system_to_stage(CoreStage::PreUpdate, hover.after(locate_pointer));
+    }
+}
+
+/// What the player asks for, from the mouse, the keyboard or a gamepad
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum Action {
+    /// Main pointer button pressed or finger down, to click buttons and drag veggies and tiles
+    Press,
+    /// Main pointer button released or finger lifted, to drop what is dragged
+    Release,
+    /// Secondary pointer button, to lock veggies as givens in the editor
+    Lock,
+    /// Held to move the garden camera around with the pointer
+    Pan,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Click the selected button, plant the held veggie or pick up the one under the grid cursor
+    Confirm,
+    /// Give the held or dragged veggie back to the inventory
+    Back,
+    /// Click the leave button of the screen
+    Leave,
+    NextButton,
+    PreviousButton,
+    NextVeggie,
+    PreviousVeggie,
+    /// Pick a veggie of the inventory by its place
+    Veggie(usize),
+    /// Open or close the pause menu
+    Menu,
+    /// Switch between fullscreen and windowed
+    Fullscreen,
+}
+
+/// Actions of the current frame
+#[derive(Resource, Default)]
+pub struct Actions {
+    just_pressed: HashSet<Action>,
+    pressed: HashSet<Action>,
+}
+
+impl Actions {
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn any_just_pressed(&self, actions: impl IntoIterator<Item=Action>) -> bool {
+        actions.into_iter().any(|a| self.just_pressed(a))
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// Place in the inventory of the veggie picked with the number keys
+    pub fn veggie(&self) -> Option<usize> {
+        self.just_pressed.iter().find_map(|a| match a {
+            Action::Veggie(i) => Some(*i),
+            _ => None,
+        })
+    }
+
+    fn add(&mut self, action: Action, just_pressed: bool) {
+        self.pressed.insert(action);
+        if just_pressed { self.just_pressed.insert(action); }
+    }
+}
+
+/// Mouse or finger, whichever was used last
+#[derive(Resource, Default)]
+pub struct Pointer {
+    /// Position in the window
+    pub position: Option<Vec2>,
+    /// The position comes from a finger
+    pub touch: bool,
+    /// World positions under the pointer, seen through the UI and the garden cameras
+    pub ui: Option<Vec2>,
+    pub garden: Option<Vec2>,
+    /// World positions where dragged veggies and tiles are held
+    pub drag_ui: Option<Vec2>,
+    pub drag_garden: Option<Vec2>,
+    last_mouse: Option<Vec2>,
+    press: Option<TouchPress>,
+}
+
+#[derive(Copy, Clone)]
+struct TouchPress {
+    id: u64,
+    /// Where and when the finger last came to rest
+    start: Vec2,
+    time: f32,
+    /// Already held long enough to give back the veggie
+    long: bool,
+}
+
+/// Dragged veggies are held above the finger so that it doesn't hide them
+const FINGER_LIFT: Vec2 = Vec2::new(0., 48.);
+
+/// Holding a finger still that long gives the dragged veggie back to the inventory
+const LONG_PRESS: f32 = 0.8;
+
+/// Distance a finger can slip during a long press
+const LONG_PRESS_SLOP: f32 = 8.;
+
+impl Pointer {
+    /// Position in the window where dragged veggies and tiles are held
+    pub fn drag_position(&self) -> Option<Vec2> {
+        self.position.map(|pos| if self.touch { pos + FINGER_LIFT } else { pos })
+    }
+
+    /// Follow the mouse and the fingers in the window, returns the actions of the touches.
+    /// A lifted finger leaves its last position for the drop.
+    pub fn update(&mut self, mouse: Option<Vec2>, fingers: &[(u64, Vec2)], time: f32) -> Vec<Action> {
+        let mut actions = vec![];
+
+        if let Some(press) = self.press.as_mut() {
+            match fingers.iter().find(|(id, _)| *id == press.id) {
+                Some((_, pos)) => {
+                    self.position = Some(*pos);
+                    if pos.distance(press.start) >= LONG_PRESS_SLOP {
+                        // Dragging, the long press starts over once the finger stops
+                        press.start = *pos;
+                        press.time = time;
+                    } else if !press.long && time - press.time >= LONG_PRESS {
+                        press.long = true;
+                        actions.push(Action::Back);
+                    }
+                }
+                None => {
+                    self.press = None;
+                    actions.push(Action::Release);
+                }
+            }
+        } else if let Some((id, pos)) = fingers.first() {
+            self.press = Some(TouchPress { id: *id, start: *pos, time, long: false });
+            self.touch = true;
+            self.position = Some(*pos);
+            actions.push(Action::Press);
+        } else if mouse != self.last_mouse || !self.touch {
+            self.touch = false;
+            self.position = mouse;
+        }
+
+        self.last_mouse = mouse;
+        actions
+    }
+}
+
+/// Rect of an entity that the pointer can hover and click
+#[derive(Component, Copy, Clone, Default)]
+pub struct Clickable {
+    /// Bottom left corner of the rect, from the translation of the entity
+    pub offset: Vec2,
+    pub size: Vec2,
+    /// The pointer is over the rect
+    pub hovered: bool,
+}
+
+impl Clickable {
+    pub fn new(offset: Vec2, size: Vec2) -> Self {
+        Clickable { offset, size, hovered: false }
+    }
+
+    pub fn square(side: f32) -> Self {
+        Clickable::new(Vec2::ZERO, Vec2::splat(side))
+    }
+
+    pub fn contains(&self, transform: &Transform, pos: Vec2) -> bool {
+        util::collides(transform.translation + self.offset.extend(0.), self.size.x, self.size.y, pos)
+    }
+}
+
+const KEYS: [(KeyCode, Action); 19] = [
+    (KeyCode::Up, Action::Up),
+    (KeyCode::Down, Action::Down),
+    (KeyCode::Left, Action::Left),
+    (KeyCode::Right, Action::Right),
+    (KeyCode::Space, Action::Confirm),
+    (KeyCode::Return, Action::Confirm),
+    (KeyCode::Back, Action::Back),
+    (KeyCode::Escape, Action::Menu),
+    (KeyCode::F11, Action::Fullscreen),
+    (KeyCode::Key1, Action::Veggie(0)),
+    (KeyCode::Key2, Action::Veggie(1)),
+    (KeyCode::Key3, Action::Veggie(2)),
+    (KeyCode::Key4, Action::Veggie(3)),
+    (KeyCode::Key5, Action::Veggie(4)),
+    (KeyCode::Key6, Action::Veggie(5)),
+    (KeyCode::Key7, Action::Veggie(6)),
+    (KeyCode::Key8, Action::Veggie(7)),
+    (KeyCode::Key9, Action::Veggie(8)),
+    (KeyCode::Key0, Action::Veggie(9)),
+];
+
+/// B gives the held veggie back, or leaves the screen when nothing is held
+const PAD_BUTTONS: [(GamepadButtonType, &'static [Action]); 12] = [
+    (GamepadButtonType::DPadUp, &[Action::Up]),
+    (GamepadButtonType::DPadDown, &[Action::Down]),
+    (GamepadButtonType::DPadLeft, &[Action::Left]),
+    (GamepadButtonType::DPadRight, &[Action::Right]),
+    (GamepadButtonType::South, &[Action::Confirm]),
+    (GamepadButtonType::East, &[Action::Back, Action::Leave]),
+    (GamepadButtonType::LeftTrigger, &[Action::PreviousVeggie]),
+    (GamepadButtonType::RightTrigger, &[Action::NextVeggie]),
+    (GamepadButtonType::LeftTrigger2, &[Action::PreviousButton]),
+    (GamepadButtonType::RightTrigger2, &[Action::NextButton]),
+    (GamepadButtonType::Select, &[Action::NextButton]),
+    (GamepadButtonType::Start, &[Action::Menu]),
+];
+
+fn read_actions(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    pad_buttons: Res<Input<GamepadButton>>,
+    mut actions: ResMut<Actions>,
+) {
+    actions.just_pressed.clear();
+    actions.pressed.clear();
+
+    for (button, action) in [(MouseButton::Left, Action::Press), (MouseButton::Right, Action::Lock), (MouseButton::Middle, Action::Pan)] {
+        if mouse.pressed(button) { actions.add(action, mouse.just_pressed(button)); }
+    }
+    if mouse.just_released(MouseButton::Left) { actions.add(Action::Release, true); }
+
+    for (key, action) in KEYS {
+        if keys.pressed(key) { actions.add(action, keys.just_pressed(key)); }
+    }
+
+    // Shift+Tab goes back through the buttons
+    if keys.pressed(KeyCode::Tab) {
+        let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+        actions.add(if shift { Action::PreviousButton } else { Action::NextButton }, keys.just_pressed(KeyCode::Tab));
+    }
+
+    for gamepad in gamepads.iter() {
+        for (button_type, pad_actions) in PAD_BUTTONS {
+            let button = GamepadButton::new(gamepad, button_type);
+            if !pad_buttons.pressed(button) { continue; }
+            for action in pad_actions {
+                actions.add(*action, pad_buttons.just_pressed(button));
+            }
+        }
+    }
+}
+
+fn read_pointer(
+    windows: Res<Windows>,
+    touches: Res<Touches>,
+    time: Res<Time>,
+    mut pointer: ResMut<Pointer>,
+    mut actions: ResMut<Actions>,
+) {
+    let mouse = windows.get_primary().and_then(|w| w.cursor_position());
+    let fingers = touches.iter().map(|t| (t.id(), t.position())).collect::<Vec<_>>();
+
+    for action in pointer.update(mouse, &fingers, time.elapsed_seconds()) {
+        actions.add(action, true);
+    }
+    if pointer.press.is_some() { actions.add(Action::Press, false); }
+}
+
+/// World positions go through the real cameras so that they follow the size of the window
+fn locate_pointer(
+    windows: Res<Windows>,
+    mut pointer: ResMut<Pointer>,
+    ui_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    garden_camera: Query<(&Camera, &GlobalTransform), (With<GardenCamera>, Without<MainCamera>)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let to_world = |(camera, transform): (&Camera, &GlobalTransform), pos: Option<Vec2>| {
+        // The letterboxed viewport is placed from the top left corner of the window
+        let corner = camera.viewport.as_ref().map_or(Vec2::ZERO, |v| Vec2::new(
+            v.physical_position.x as f32,
+            window.physical_height() as f32 - (v.physical_position.y + v.physical_size.y) as f32,
+        ) / window.scale_factor() as f32);
+        pos.and_then(|pos| camera.viewport_to_world(transform, pos - corner)).map(|ray| ray.origin.truncate())
+    };
+    let (ui_camera, garden_camera) = (ui_camera.single(), garden_camera.single());
+    let (position, drag_position) = (pointer.position, pointer.drag_position());
+
+    pointer.ui = to_world(ui_camera, position);
+    pointer.garden = to_world(garden_camera, position);
+    pointer.drag_ui = to_world(ui_camera, drag_position);
+    pointer.drag_garden = to_world(garden_camera, drag_position);
+}
+
+/// Entities of the garden are seen through the garden camera
+fn hover(
+    pointer: Res<Pointer>,
+    mut query: Query<(&mut Clickable, &Transform, Option<&RenderLayers>)>,
+) {
+    let garden = RenderLayers::layer(GARDEN_LAYER);
+    for (mut clickable, t, layers) in query.iter_mut() {
+        let pos = if layers.map_or(false, |l| l.intersects(&garden)) { pointer.garden } else { pointer.ui };
+        let hovered = pos.map_or(false, |pos| clickable.contains(t, pos));
+        if clickable.hovered != hovered { clickable.hovered = hovered; }
+    }
+}
+
+#[test]
+fn test_clickable() {
+    let clickable = Clickable::new(Vec2::new(0., -8.), Vec2::new(24., 16.));
+    let t = Transform::from_xyz(100., 50., 0.);
+    assert!(clickable.contains(&t, Vec2::new(101., 43.)));
+    assert!(clickable.contains(&t, Vec2::new(123., 57.)));
+    assert!(!clickable.contains(&t, Vec2::new(101., 59.)));
+    assert!(!clickable.contains(&t, Vec2::new(99., 50.)));
+}
+
+#[test]
+fn test_touch_drag() {
+    let mut pointer = Pointer::default();
+    assert_eq!(pointer.update(None, &[(3, Vec2::new(10., 10.))], 0.), vec![Action::Press]);
+    assert_eq!(pointer.drag_position(), Some(Vec2::new(10., 10.) + FINGER_LIFT));
+
+    assert!(pointer.update(None, &[(3, Vec2::new(50., 10.))], 0.5).is_empty());
+    assert!(pointer.update(None, &[(3, Vec2::new(50., 10.))], 1.2).is_empty());
+
+    // The drop happens where the finger was lifted
+    assert_eq!(pointer.update(None, &[], 1.6), vec![Action::Release]);
+    assert_eq!(pointer.position, Some(Vec2::new(50., 10.)));
+
+    // Until the mouse moves
+    assert!(pointer.update(Some(Vec2::new(0., 0.)), &[], 1.7).is_empty());
+    assert_eq!(pointer.drag_position(), Some(Vec2::new(0., 0.)));
+}
+
+#[test]
+fn test_long_press() {
+    let mut pointer = Pointer::default();
+    pointer.update(None, &[(0, Vec2::new(10., 10.))], 0.);
+    assert!(pointer.update(None, &[(0, Vec2::new(12., 10.))], LONG_PRESS / 2.).is_empty());
+    assert_eq!(pointer.update(None, &[(0, Vec2::new(12., 10.))], LONG_PRESS), vec![Action::Back]);
+    assert!(pointer.update(None, &[(0, Vec2::new(12., 10.))], LONG_PRESS * 2.).is_empty());
+    assert_eq!(pointer.update(None, &[], LONG_PRESS * 2.), vec![Action::Release]);
+}
+
+#[test]
+fn test_drag_then_hold() {
+    let mut pointer = Pointer::default();
+    pointer.update(None, &[(0, Vec2::new(10., 10.))], 0.);
+    assert!(pointer.update(None, &[(0, Vec2::new(50., 10.))], 1.).is_empty());
+
+    // Held still after the drag
+    assert!(pointer.update(None, &[(0, Vec2::new(52., 10.))], 1.5).is_empty());
+    assert_eq!(pointer.update(None, &[(0, Vec2::new(52., 10.))], 2.), vec![Action::Back]);
+}
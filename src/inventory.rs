@@ -6,12 +6,14 @@ use bevy_text_mode::TextModeTextureAtlasSprite;
 use bevy_tweening::Animator;
 use strum::IntoEnumIterator;
 
-use crate::{BlockInput, GameState, HEIGHT, util};
+use crate::{BlockInput, GameState, GardenCamera, HEIGHT, MainCamera, util};
 use crate::audio::{PlaySfxEvent, SFX};
 use crate::editor::DraggedTile;
-use crate::grid::{CurrentPuzzle, DisplayLevel, GridChanged, GridUI, GridVeggie, PreviousPos};
+use crate::grid::{Cursor, CurrentPuzzle, DisplayLevel, garden_layer, get_tile_pos, GridChanged, GridUI, GridVeggie, Locked, PreviousPos};
+use crate::input::{Action, Actions, Clickable, Pointer};
 use crate::loading::Textures;
-use crate::text::{ChangeText, spawn_text};
+use crate::play::Moves;
+use crate::text::{ButtonFocus, ChangeText, spawn_text};
 use crate::util::Colors;
 use crate::veggie::{Expression, spawn_veggie, UpdateFaces, Veggie};
 
@@ -36,8 +38,12 @@ impl Plugin for InventoryPlugin {
 #[derive(Component)]
 struct InventoryUI;
 
+/// Veggie of the inventory, with its place in it for the number keys
 #[derive(Component)]
-struct InventoryVeg(Veggie);
+struct InventoryVeg(Veggie, usize);
+
+/// Veggies per column of the inventory
+const MAX_ROWS: usize = 7;
 
 #[derive(Component)]
 struct InventoryCount(Veggie);
@@ -48,10 +54,13 @@ fn display(
     puzzle: Res<CurrentPuzzle>,
     textures: Res<Textures>,
     state: Res<State<GameState>>,
+    entities: Query<Entity, (With<InventoryUI>, Without<DraggedVeg>)>,
 ) {
     let in_editor = state.current() == &GameState::Editor;
 
     for _ in event.iter() {
+        entities.iter().for_each(|e| commands.entity(e).despawn_recursive());
+
         if let Some(puzzle) = &puzzle.0 {
             let all_veggies = if in_editor {
                 Veggie::iter().map(|v| (v, 99)).collect::<Vec<(Veggie, u8)>>()
@@ -59,26 +68,37 @@ fn display(
                 puzzle.veggies.iter().map(|(v, c)| (*v, *c)).collect::<Vec<(Veggie, u8)>>()
             };
 
-            let h = (HEIGHT - all_veggies.len() as f32 * 48.) / 2.;
+            // Split in columns when the veggies don't fit in one
+            let columns = (all_veggies.len() + MAX_ROWS - 1) / MAX_ROWS;
+            let rows = (all_veggies.len() + columns.max(1) - 1) / columns.max(1);
+            let column_chars = if in_editor { 6 } else { 11 };
+
+            let h = (HEIGHT - rows as f32 * 48.) / 2.;
             let w = 24.;
             for (i, (veg, count)) in all_veggies.iter().enumerate() {
+                let x = w + (column_chars * (i / rows)) as f32 * 8.;
+                let y = h + 48. * (i % rows) as f32;
+                let veg_pos = Vec3::new(x + if in_editor { 16. } else { 0. }, y + 4., util::z::VEG_UI);
                 let veg_e = spawn_veggie(
                     &mut commands,
                     &textures,
-                    Vec3::new(w + if in_editor { 16. } else { 0. }, h + 48. * i as f32 + 4., util::z::VEG_UI),
+                    veg_pos,
                     veg,
                     Expression::Neutral,
                 );
+                // Big veggies are shrunk to a tile
+                let scale = 1. / veg.span() as f32;
                 commands
                     .entity(veg_e)
+                    .insert(Transform::from_translation(veg_pos).with_scale(Vec3::new(scale, scale, 1.)))
                     .insert(InventoryUI)
-                    .insert(InventoryVeg(veg.clone()));
+                    .insert(InventoryVeg(veg.clone(), i));
 
                 if !in_editor {
                     let text = spawn_text(
                         &mut commands,
                         &textures,
-                        Vec3::new(w + 40., h + 48. * i as f32 + 8., util::z::COUNT_TEXT),
+                        Vec3::new(x + 40., y + 8., util::z::COUNT_TEXT),
                         &format!("x{:0>2}", count),
                         Colors::Beige,
                         Colors::DarkRed,
@@ -95,7 +115,7 @@ fn display(
             let id = util::frame(
                 &mut commands, &textures,
                 w - 8., h, util::z::VEG_UI_BG,
-                11, all_veggies.len() * 6,
+                11 + column_chars * (columns.max(1) - 1), rows * 6,
                 Colors::DarkRed, Colors::Beige
             );
 
@@ -132,143 +152,234 @@ fn update_counts(
 #[derive(Component)]
 pub struct DraggedVeg(pub Veggie);
 
+/// Dragged veggie carried with the keyboard or a gamepad: it hovers above the grid cursor
+/// instead of following the mouse
+#[derive(Component)]
+pub struct Held;
+
 fn handle_click(
     mut commands: Commands,
-    inventory: Query<(&InventoryVeg, &Transform)>,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    inventory: Query<(&InventoryVeg, &Transform, &Clickable)>,
+    actions: Res<Actions>,
+    pointer: Res<Pointer>,
     textures: Res<Textures>,
     puzzle: Res<CurrentPuzzle>,
     state: Res<State<GameState>>,
     block_input: Res<BlockInput>,
+    moves: Res<Moves>,
+    mut cursor: ResMut<Cursor>,
+    dragged: Query<(Entity, Option<&Held>, Option<&PreviousPos>), With<DraggedVeg>>,
+    mut selected: Local<usize>,
     mut sfx: EventWriter<PlaySfxEvent>,
 ) {
     let in_editor = state.current() == &GameState::Editor;
     if puzzle.0.is_none() || block_input.0 { return; }
     let puzzle = puzzle.0.as_ref().unwrap();
 
-    if mouse.just_pressed(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
-            if let Some((v, _)) = inventory.iter().filter(|(_, t)|
-                (t.translation.x + 20. - pos.x / 2.).abs() < 20.
-                && (t.translation.y + 20. - pos.y / 2.).abs() < 20.
-            ).nth(0) {
-                if puzzle.remaining_veggie(&v.0, in_editor) == 0 { return; }
+    // Number keys pick the veggies in the order of the inventory, the shoulder buttons
+    // cycle through the ones left and swap the veggie held from the inventory
+    let cycle = if actions.just_pressed(Action::NextVeggie) {
+        1
+    } else if actions.just_pressed(Action::PreviousVeggie) {
+        -1
+    } else {
+        0
+    };
+    let swap = !dragged.is_empty() && dragged.iter().all(|(_, held, prev)| held.is_some() && prev.is_none());
+    let key = match actions.veggie() {
+        Some(i) if dragged.is_empty() => Some(i),
+        _ if cycle != 0 && (dragged.is_empty() || swap) => {
+            let n = inventory.iter().count() as isize;
+            let first = if swap { 1 } else { 0 };
+            (first..=n)
+                .map(|k| (*selected as isize + cycle * k).rem_euclid(n.max(1)) as usize)
+                .find(|i| inventory.iter().any(|(v, _, _)| v.1 == *i && puzzle.remaining_veggie(&v.0, in_editor) > 0))
+        },
+        _ => None,
+    };
+    if key.is_some() && swap {
+        dragged.iter().for_each(|(e, _, _)| commands.entity(e).despawn_recursive());
+    }
 
-                sfx.send(PlaySfxEvent(SFX::Clic));
+    if actions.just_pressed(Action::Press) || key.is_some() {
+        if let Some((v, t, _)) = inventory.iter().filter(|(v, _, clickable)| match key {
+            Some(i) => v.1 == i,
+            None => clickable.hovered,
+        }).nth(0) {
+            if puzzle.remaining_veggie(&v.0, in_editor) == 0 { return; }
+            if !in_editor && puzzle.out_of_moves(moves.count) {
+                sfx.send(PlaySfxEvent(SFX::Error));
+                return;
+            }
 
-                // Spawn a veggie
-                let veg_e = spawn_veggie(
-                    &mut commands,
-                    &textures,
-                    Vec3::new(pos.x / 2. - 20., pos.y / 2. - 20. + 8., util::z::VEG_DRAG),
-                    &v.0,
-                    Expression::Surprised,
-                );
-                commands
-                    .entity(veg_e)
-                    .insert(InventoryUI)
-                    .insert(DraggedVeg(v.0.clone()));
+            sfx.send(PlaySfxEvent(SFX::PickUp(v.0)));
+
+            // Spawn a veggie
+            let offset = 20. * v.0.span() as f32;
+            let pos = match (key, pointer.drag_ui) {
+                (Some(_), _) => t.translation.truncate() + offset,
+                (None, Some(pos)) => pos,
+                _ => return,
+            };
+            let veg_e = spawn_veggie(
+                &mut commands,
+                &textures,
+                Vec3::new(pos.x - offset, pos.y - offset + 8., util::z::VEG_DRAG),
+                &v.0,
+                Expression::Surprised,
+            );
+            commands
+                .entity(veg_e)
+                .insert(InventoryUI)
+                .insert(DraggedVeg(v.0.clone()));
+            if let Some(i) = key {
+                commands.entity(veg_e).insert(Held);
+                cursor.visible = true;
+                *selected = i;
             }
         }
     }
 }
 
 fn update_dragged(
-    mut query: Query<&mut Transform, Or<(With<DraggedVeg>, With<DraggedTile>)>>,
-    windows: Res<Windows>,
+    mut query: Query<(&mut Transform, Option<&DraggedVeg>, Option<&Held>), (Or<(With<DraggedVeg>, With<DraggedTile>)>, Without<MainCamera>, Without<GardenCamera>)>,
+    pointer: Res<Pointer>,
+    puzzle: Res<CurrentPuzzle>,
+    cursor: Res<Cursor>,
+    camera: Query<&Transform, With<MainCamera>>,
+    garden_camera: Query<&Transform, With<GardenCamera>>,
 ) {
-    let window = windows.get_primary().unwrap();
-    if let Some(pos) = window.cursor_position() {
-        query.iter_mut().for_each(|mut t| {
-            t.translation.x = pos.x / 2. - 20.;
-            t.translation.y = pos.y / 2. - 20. + 8.;
-        })
-    }
+    let (camera, garden_camera) = (camera.single(), garden_camera.single());
+
+    // Held veggies hover above the tile of the grid cursor
+    let above_cursor = puzzle.0.as_ref().map(|puzzle| {
+        util::world_to_world(get_tile_pos(cursor.pos, puzzle) + Vec2::new(20., 28.), garden_camera, camera)
+    });
+
+    query.iter_mut().for_each(|(mut t, veggie, held)| {
+        let pos = if held.is_some() { above_cursor } else { pointer.drag_ui };
+        if let Some(pos) = pos {
+            let offset = 20. * veggie.map_or(1, |v| v.0.span()) as f32;
+            t.translation.x = pos.x - offset;
+            t.translation.y = pos.y - offset + 8.;
+        }
+    })
 }
 
 fn handle_drop(
     mut commands: Commands,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
-    query: Query<(Entity, &DraggedVeg, &Transform, &Children, Option<&PreviousPos>)>,
+    actions: Res<Actions>,
+    pointer: Res<Pointer>,
+    cursor: Res<Cursor>,
+    focus: Res<ButtonFocus>,
+    query: Query<(Entity, &DraggedVeg, &Transform, &Children, Option<&PreviousPos>, Option<&Locked>, Option<&Held>)>,
     mut puzzle: ResMut<CurrentPuzzle>,
     mut update_faces: EventWriter<UpdateFaces>,
     mut grid_changed: EventWriter<GridChanged>,
     mut sfx: EventWriter<PlaySfxEvent>,
+    mut moves: ResMut<Moves>,
+    state: Res<State<GameState>>,
+    ui_camera: Query<&Transform, (With<MainCamera>, Without<DraggedVeg>)>,
+    garden_camera: Query<&Transform, (With<GardenCamera>, Without<DraggedVeg>)>,
 ) {
+    let in_editor = state.current() == &GameState::Editor;
     if puzzle.0.is_none() { return; }
     let puzzle = puzzle.0.as_mut().unwrap();
 
     let animation_len = 1000;
 
-    if mouse.just_released(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
-            for (e, v, t, c, prev) in query.iter() {
-                commands.entity(e)
-                    .remove::<DraggedVeg>()
-                    .insert(GridUI);
-
-                // Drop on a free tile of the grid -> animate to pos + update count
-                if let Some(tile) = crate::grid::get_pos_at(pos, puzzle.size) {
-                    let destination = if !puzzle.placed.contains_key(&tile) && !puzzle.tiles.contains_key(&tile) {
-                        Some(tile)
-                    } else if prev.is_some() {
-                        Some(prev.unwrap().0)
-                    } else {
-                        None
-                    };
-                    if destination.is_some() {
-                        sfx.send(PlaySfxEvent(SFX::Place));
-
-                        let tile = destination.unwrap();
-                        puzzle.placed.insert(tile, v.0.clone());
-
-                        commands
-                            .entity(e)
-                            .remove::<PreviousPos>()
-                            .insert(Animator::<Transform>::new(
-                                crate::tween::position_out(
-                                    t.translation.xy(),
-                                    crate::grid::get_tile_pos(tile, puzzle.size),
-                                    util::z::VEGGIE,
-                                    animation_len / 2
-                                )
-                            ))
-                            .insert(GridVeggie(v.0.clone(), tile, (false, false)));
-
-                        grid_changed.send(GridChanged);
-
-                        continue
+    // Confirm plants the held veggie under the grid cursor, Back or a long press
+    // gives it back to the inventory
+    let mouse_drop = actions.just_pressed(Action::Release);
+    let key_drop = focus.0.is_none() && actions.just_pressed(Action::Confirm);
+    let key_back = actions.just_pressed(Action::Back);
+
+    if mouse_drop || key_drop || key_back {
+        let (ui_camera, garden_camera) = (ui_camera.single(), garden_camera.single());
+
+        for (e, v, t, c, prev, locked, held) in query.iter() {
+            let target = match (held, pointer.drag_garden) {
+                (Some(_), _) if key_drop => {
+                    // Keep holding it until it fits
+                    if !puzzle.fits(&v.0, cursor.pos) {
+                        sfx.send(PlaySfxEvent(SFX::Error));
+                        continue;
                     }
-                }
+                    Some(cursor.pos)
+                },
+                _ if key_back => None,
+                // Big veggies are dropped by their bottom left tile
+                (None, Some(pos)) if mouse_drop => crate::grid::get_pos_at(pos - Vec2::splat(20. * (v.0.span() - 1) as f32), puzzle),
+                _ => continue,
+            };
 
-                // Else -> disappear animation
-                commands
-                    .entity(e)
-                    .insert(Animator::<Transform>::new(
-                        crate::tween::position_out(
-                            t.translation.xy(),
-                            t.translation.xy().add(Vec2::new(0., 24.)),
-                            t.translation.z,
-                            3 * animation_len
-                        )
-                    ))
-                    .insert(Animator::<TextureAtlasSprite>::new(
-                        crate::tween::tween_texture_atlas_sprite_opacity(animation_len, false)
-                    ));
+            commands.entity(e)
+                .remove::<DraggedVeg>()
+                .remove::<Held>()
+                .insert(GridUI);
+
+            // Drop on a free tile of the grid -> animate to pos + update count
+            if let Some(tile) = target {
+                let destination = if puzzle.fits(&v.0, tile) {
+                    Some(tile)
+                } else if prev.is_some() {
+                    Some(prev.unwrap().0)
+                } else {
+                    None
+                };
+                if destination.is_some() {
+                    sfx.send(PlaySfxEvent(SFX::Plant(v.0)));
+
+                    // Veggies moved around the garden already paid when picked up
+                    if prev.is_none() && !in_editor { moves.count += 1; }
+
+                    let tile = destination.unwrap();
+                    puzzle.placed.insert(tile, v.0.clone());
+                    if locked.is_some() { puzzle.givens.insert(tile); }
 
-                for face in c {
                     commands
-                        .entity(*face)
-                        .insert(Animator::<TextModeTextureAtlasSprite>::new(
-                            crate::tween::tween_text_mode_sprite_opacity(animation_len, false)
-                        ));
+                        .entity(e)
+                        .remove::<PreviousPos>()
+                        .insert(garden_layer())
+                        .insert(Animator::<Transform>::new(
+                            crate::tween::position_out(
+                                util::world_to_world(t.translation.xy(), ui_camera, garden_camera),
+                                crate::grid::get_tile_pos(tile, puzzle),
+                                util::z::VEGGIE,
+                                animation_len / 2
+                            )
+                        ))
+                        .insert(GridVeggie(v.0.clone(), tile, (false, false)));
+
+                    grid_changed.send(GridChanged);
+
+                    continue
                 }
-                update_faces.send(UpdateFaces(e, (Expression::Sad, Expression::Sad)));
             }
+
+            // Else -> disappear animation
+            commands
+                .entity(e)
+                .insert(Animator::<Transform>::new(
+                    crate::tween::position_out(
+                        t.translation.xy(),
+                        t.translation.xy().add(Vec2::new(0., 24.)),
+                        t.translation.z,
+                        3 * animation_len
+                    )
+                ))
+                .insert(Animator::<TextureAtlasSprite>::new(
+                    crate::tween::tween_texture_atlas_sprite_opacity(animation_len, false)
+                ));
+
+            for face in c {
+                commands
+                    .entity(*face)
+                    .insert(Animator::<TextModeTextureAtlasSprite>::new(
+                        crate::tween::tween_text_mode_sprite_opacity(animation_len, false)
+                    ));
+            }
+            update_faces.send(UpdateFaces(e, (Expression::Sad, Expression::Sad)));
         }
     }
 }
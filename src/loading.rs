@@ -1,19 +1,27 @@
+use std::collections::HashMap;
+
+use bevy::asset::LoadState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::AudioSource;
 
+use crate::audio::SoundTable;
 use crate::GameState;
 
 pub struct LoadingPlugin;
 
 impl Plugin for LoadingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_loading_state(
-            LoadingState::new(GameState::Loading)
-                .with_collection::<AudioAssets>()
-                .with_collection::<Textures>()
-                .continue_to_state(GameState::Title),
-        );
+        app
+            .add_loading_state(
+                LoadingState::new(GameState::Loading)
+                    .with_collection::<AudioAssets>()
+                    .with_collection::<Textures>()
+                    .continue_to_state(GameState::LoadingSounds),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::LoadingSounds).with_system(load_effects))
+            .add_system_set(SystemSet::on_update(GameState::LoadingSounds).with_system(check_effects))
+        ;
     }
 }
 
@@ -21,28 +29,60 @@ impl Plugin for LoadingPlugin {
 pub struct AudioAssets {
     #[asset(path = "BGM + SFX/BGM - 1 - title.ogg")]
     pub title: Handle<AudioSource>,
-    #[asset(path = "BGM + SFX/BGM - 2 - main theme.ogg")]
-    pub level: Handle<AudioSource>,
+    /// Mixes of the main theme, from the muffled one of a sad garden to the full arrangement
+    #[asset(paths("BGM + SFX/BGM - 2 - main theme - muffled.wav", "BGM + SFX/BGM - 2 - main theme.ogg"), collection(typed))]
+    pub level: Vec<Handle<AudioSource>>,
     #[asset(path = "BGM + SFX/BGM - 3 - editor.ogg")]
     pub editor: Handle<AudioSource>,
 
-    #[asset(path = "BGM + SFX/clic.ogg")]
-    pub clic: Handle<AudioSource>,
-    #[asset(path = "BGM + SFX/error.ogg")]
-    pub error: Handle<AudioSource>,
-    #[asset(path = "BGM + SFX/place.ogg")]
-    pub place: Handle<AudioSource>,
-    #[asset(path = "BGM + SFX/sfx.ogg")]
-    pub win: Handle<AudioSource>,
+    #[asset(path = "effects.sounds.ron")]
+    pub sounds: Handle<SoundTable>,
+}
+
+/// Files of the sound effects by path, loaded from the sound table before the game starts
+#[derive(Resource, Default)]
+pub struct Effects(pub HashMap<String, Handle<AudioSource>>);
+
+fn load_effects(
+    mut commands: Commands,
+    audio_assets: Res<AudioAssets>,
+    tables: Res<Assets<SoundTable>>,
+    asset_server: Res<AssetServer>,
+) {
+    let mut effects = Effects::default();
+    if let Some(table) = tables.get(&audio_assets.sounds) {
+        for file in table.files() {
+            effects.0.entry(file.clone()).or_insert_with(|| asset_server.load(file.as_str()));
+        }
+    }
+    commands.insert_resource(effects);
+}
+
+fn check_effects(
+    effects: Option<Res<Effects>>,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if effects.is_none() { return; }
+
+    // A missing file leaves its sound silent instead of blocking the game
+    let done = effects.unwrap().0.values().all(|handle| {
+        matches!(asset_server.get_load_state(handle), LoadState::Loaded | LoadState::Failed)
+    });
+    if done { state.set(GameState::Title).unwrap_or_default(); }
 }
 
 #[derive(AssetCollection, Resource)]
 pub struct Textures {
-    #[asset(texture_atlas(tile_size_x = 40., tile_size_y = 40., columns = 7, rows = 1, padding_x = 0., padding_y = 0.))]
+    #[asset(texture_atlas(tile_size_x = 40., tile_size_y = 40., columns = 12, rows = 1, padding_x = 0., padding_y = 0.))]
     #[asset(path = "veggies.png")]
     pub fruit: Handle<TextureAtlas>,
 
-    #[asset(texture_atlas(tile_size_x = 8., tile_size_y = 8., columns = 4, rows = 1, padding_x = 0., padding_y = 0.))]
+    #[asset(texture_atlas(tile_size_x = 80., tile_size_y = 80., columns = 1, rows = 1, padding_x = 0., padding_y = 0.))]
+    #[asset(path = "big_veggies.png")]
+    pub big_fruit: Handle<TextureAtlas>,
+
+    #[asset(texture_atlas(tile_size_x = 8., tile_size_y = 8., columns = 5, rows = 1, padding_x = 0., padding_y = 0.))]
     #[asset(path = "faces.png")]
     pub faces: Handle<TextureAtlas>,
 
@@ -50,10 +90,19 @@ pub struct Textures {
     #[asset(path = "border.png")]
     pub border: Handle<TextureAtlas>,
 
-    #[asset(texture_atlas(tile_size_x = 40., tile_size_y = 40., columns = 4, rows = 1, padding_x = 2., padding_y = 0.))]
+    #[asset(texture_atlas(tile_size_x = 40., tile_size_y = 40., columns = 7, rows = 1, padding_x = 2., padding_y = 0.))]
     #[asset(path = "tile.png")]
     pub tile: Handle<TextureAtlas>,
 
+    /// Channels by connected sides (east 1, north 2, west 4, south 8), dry then two wet frames
+    #[asset(texture_atlas(tile_size_x = 40., tile_size_y = 40., columns = 16, rows = 3, padding_x = 0., padding_y = 0.))]
+    #[asset(path = "channel.png")]
+    pub channel: Handle<TextureAtlas>,
+
+    #[asset(texture_atlas(tile_size_x = 48., tile_size_y = 58., columns = 4, rows = 1, padding_x = 0., padding_y = 0.))]
+    #[asset(path = "hex.png")]
+    pub hex: Handle<TextureAtlas>,
+
     #[asset(texture_atlas(tile_size_x = 8., tile_size_y = 8., columns = 32, rows = 32, padding_x = 0., padding_y = 0.))]
     #[asset(path = "MRMOTEXT EX.png")]
     pub mrmotext: Handle<TextureAtlas>,
@@ -63,4 +112,7 @@ pub struct Textures {
 
     #[asset(path = "heart.png")]
     pub heart: Handle<Image>,
+
+    #[asset(path = "fence.png")]
+    pub fence: Handle<Image>,
 }
\ No newline at end of file
@@ -1,6 +1,9 @@
 // let_(thinking) = rof if(shrink)
 
+use bevy::core_pipeline::clear_color::ClearColorConfig;
 use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy::render::view::RenderLayers;
 use bevy_pkv::PkvStore;
 use bevy_text_mode::{TextModePlugin, TextModeTextureAtlasSprite};
 use bevy_tweening::{component_animator_system, TweeningPlugin};
@@ -8,10 +11,14 @@ use bevy_tweening::{component_animator_system, TweeningPlugin};
 use crate::audio::InternalAudioPlugin;
 use crate::editor::EditorPlugin;
 use crate::grid::GridPlugin;
+use crate::input::InputPlugin;
 use crate::inventory::InventoryPlugin;
 use crate::loading::LoadingPlugin;
 use crate::overworld::OverworldPlugin;
+use crate::pause::PausePlugin;
 use crate::play::PlayPlugin;
+use crate::screen::ScreenPlugin;
+use crate::settings::SettingsPlugin;
 use crate::text::TextPlugin;
 use crate::title::TitlePlugin;
 use crate::tutorial::TutorialPlugin;
@@ -35,24 +42,42 @@ mod tutorial;
 mod progress;
 mod overworld;
 mod audio;
+mod solver;
+mod deduce;
+mod season;
+mod input;
+mod pause;
+mod settings;
+mod screen;
 
-// Dimensions in "zoomed" pixels (camera has a 2x factor)
+// Dimensions in "zoomed" pixels (camera has a 2x factor), scaled up to fit the window
 pub const WIDTH: f32 = 1280. / 2.;
 pub const HEIGHT: f32 = 720. / 2.;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Copy)]
 enum GameState {
     Loading,
+    /// The files of the sound table, once it is read
+    LoadingSounds,
     Title,
     Play,
     Editor,
     Tutorial,
     Overworld,
+    Settings,
 }
 
 #[derive(Resource)]
 pub struct BlockInput(pub bool);
 
+/// Camera of the UI, drawn above the garden
+#[derive(Component)]
+pub struct MainCamera;
+
+/// Camera looking at the garden, with pan and zoom
+#[derive(Component)]
+pub struct GardenCamera;
+
 fn main() {
     App::new()
         .insert_resource(ClearColor(Colors::DarkRed.get()))
@@ -65,6 +90,7 @@ fn main() {
                     height: HEIGHT * 2.,
                     title: "LD52".to_string(),
                     canvas: Some("#bevy".to_owned()),
+                    fit_canvas_to_parent: true,
                     ..Default::default()
                 },
                 ..default()
@@ -76,6 +102,9 @@ fn main() {
         .add_system(component_animator_system::<TextureAtlasSprite>)
         .add_system(component_animator_system::<TextModeTextureAtlasSprite>)
         .add_plugin(InternalAudioPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(SettingsPlugin)
+        .add_plugin(ScreenPlugin)
         .add_plugin(VeggiePlugin)
         .add_plugin(TextPlugin)
         .add_plugin(LoadingPlugin)
@@ -86,6 +115,7 @@ fn main() {
         .add_plugin(EditorPlugin)
         .add_plugin(InventoryPlugin)
         .add_plugin(TutorialPlugin)
+        .add_plugin(PausePlugin)
         .add_startup_system(init)
         .insert_resource(BlockInput(false))
         .insert_resource(PkvStore::new("yopox.ld52", "mad_veggies"))
@@ -93,12 +123,33 @@ fn main() {
 }
 
 fn init(mut commands: Commands) {
-    commands.spawn(Camera2dBundle {
-        transform: Transform {
-            scale: Vec3::new(0.5, 0.5, 1.),
-            translation: Vec3::new(WIDTH / 2., HEIGHT / 2., 100.),
-            ..Default::default()
-        },
+    let transform = Transform {
+        scale: Vec3::new(0.5, 0.5, 1.),
+        translation: Vec3::new(WIDTH / 2., HEIGHT / 2., 100.),
+        ..Default::default()
+    };
+    // The viewport is letterboxed by the screen plugin
+    let projection = OrthographicProjection {
+        scaling_mode: ScalingMode::Auto { min_width: WIDTH * 2., min_height: HEIGHT * 2. },
         ..Default::default()
-    });
+    };
+
+    commands
+        .spawn(Camera2dBundle {
+            camera: Camera { priority: 1, ..Default::default() },
+            camera_2d: Camera2d { clear_color: ClearColorConfig::None },
+            projection: projection.clone(),
+            transform,
+            ..Default::default()
+        })
+        .insert(MainCamera);
+
+    commands
+        .spawn(Camera2dBundle {
+            projection,
+            transform,
+            ..Default::default()
+        })
+        .insert(GardenCamera)
+        .insert(RenderLayers::layer(grid::GARDEN_LAYER));
 }
\ No newline at end of file
@@ -106,7 +106,11 @@ fn setup(
         (Slot::Level(9), 3),
         (Slot::Level(10), 3),
         (Slot::Level(11), 3),
-        (Slot::Level(12), 5),
+        (Slot::Level(12), 3),
+        (Slot::Tutorial(7), 3),
+        (Slot::Tutorial(8), 3),
+        (Slot::Tutorial(9), 3),
+        (Slot::Tutorial(10), 5),
     ] {
         let completed = match slot {
             Slot::Level(n) => progress.finished_levels.contains(&n),
@@ -0,0 +1,142 @@
+use bevy::prelude::*;
+
+use crate::{BlockInput, GameState, HEIGHT, text, util, WIDTH};
+use crate::input::{Action, Actions};
+use crate::inventory::DraggedVeg;
+use crate::loading::Textures;
+use crate::settings::{Label, Settings};
+use crate::text::{ButtonClick, TextButtonId};
+use crate::util::Colors;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        for state in [GameState::Play, GameState::Editor] {
+            app
+                .add_system_set(SystemSet::on_update(state)
+                    .with_system(toggle_pause)
+                    .with_system(click_on_button)
+                )
+                .add_system_set(SystemSet::on_exit(state).with_system(cleanup));
+        }
+    }
+}
+
+/// The pause menu is open, the garden doesn't take any input
+#[derive(Resource)]
+struct Paused;
+
+#[derive(Component)]
+struct PauseUI;
+
+/// Start or Escape opens the pause menu, and closes it like B does
+fn toggle_pause(
+    mut commands: Commands,
+    textures: Res<Textures>,
+    settings: Res<Settings>,
+    actions: Res<Actions>,
+    paused: Option<Res<Paused>>,
+    mut block_input: ResMut<BlockInput>,
+    dragged: Query<(), With<DraggedVeg>>,
+    entities: Query<Entity, With<PauseUI>>,
+) {
+    if paused.is_some() {
+        if actions.any_just_pressed([Action::Menu, Action::Leave]) {
+            close(&mut commands, block_input.as_mut(), &entities);
+        }
+        return;
+    }
+
+    // Not while something else holds the input or a veggie is in hand
+    if !actions.just_pressed(Action::Menu) || block_input.0 || !dragged.is_empty() { return; }
+
+    commands.insert_resource(Paused);
+    block_input.0 = true;
+
+    let (w, h) = (23, 10);
+    let x = ((WIDTH - (w + 2) as f32 * 8.) / 2.).round();
+    let y = ((HEIGHT - (h + 2) as f32 * 8.) / 2.).round();
+    let id = util::frame(
+        &mut commands, &textures,
+        x, y, util::z::POPUP_BG,
+        w, h,
+        Colors::DarkRed, Colors::Beige
+    );
+    commands.entity(id).insert(PauseUI);
+
+    // Texts are centered, their length depends on the language
+    let center = |text: &str| x + ((w + 2) as f32 * 8. - text.len() as f32 * 8.) / 2.;
+    let title = settings.language.translate(Label::Paused);
+    let id = text::spawn_text(
+        &mut commands, &textures,
+        Vec3::new(center(title), y + 72., util::z::POPUP),
+        title,
+        Colors::Beige, Colors::DarkRed,
+    );
+    commands.entity(id).insert(PauseUI);
+
+    for (text_y, text, button) in [
+        (y + 48., Label::Resume, TextButtonId::Resume),
+        (y + 32., Label::SettingsButton, TextButtonId::Settings),
+        (y + 16., Label::Leave, TextButtonId::Quit),
+    ] {
+        let text = settings.language.translate(text);
+        let id = text::spawn_text(
+            &mut commands, &textures,
+            Vec3::new(center(text), text_y, util::z::POPUP),
+            text,
+            Colors::Beige, Colors::DarkRed,
+        );
+        commands.entity(id)
+            .insert(PauseUI)
+            .insert(button);
+    }
+}
+
+fn close(commands: &mut Commands, block_input: &mut BlockInput, entities: &Query<Entity, With<PauseUI>>) {
+    commands.remove_resource::<Paused>();
+    block_input.0 = false;
+    entities.iter().for_each(|e| commands.entity(e).despawn_recursive());
+}
+
+fn click_on_button(
+    mut commands: Commands,
+    mut clicks: EventReader<ButtonClick>,
+    paused: Option<Res<Paused>>,
+    mut state: ResMut<State<GameState>>,
+    mut block_input: ResMut<BlockInput>,
+    entities: Query<Entity, With<PauseUI>>,
+) {
+    if paused.is_none() { return; }
+
+    for click in clicks.iter() {
+        match click.0 {
+            TextButtonId::Resume => {
+                close(&mut commands, block_input.as_mut(), &entities);
+            }
+
+            // The garden waits under the settings screen
+            TextButtonId::Settings => {
+                close(&mut commands, block_input.as_mut(), &entities);
+                state.push(GameState::Settings).unwrap_or_default();
+            }
+
+            TextButtonId::Quit => {
+                close(&mut commands, block_input.as_mut(), &entities);
+                state.pop().unwrap_or_default();
+            }
+
+            _ => {}
+        }
+    }
+}
+
+fn cleanup(
+    mut commands: Commands,
+    paused: Option<Res<Paused>>,
+    mut block_input: ResMut<BlockInput>,
+    entities: Query<Entity, With<PauseUI>>,
+) {
+    if paused.is_some() { close(&mut commands, block_input.as_mut(), &entities); }
+}
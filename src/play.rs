@@ -1,29 +1,38 @@
 use bevy::prelude::*;
 use bevy_pkv::PkvStore;
+use bevy_text_mode::TextModeTextureAtlasSprite;
 use bevy_tweening::Animator;
 
-use crate::{BlockInput, GameState, grid, HEIGHT, text, tween, util, WIDTH};
+use crate::{BlockInput, GameState, grid, HEIGHT, season, text, tween, util, WIDTH};
 use crate::audio::{BGM, PlayBgmEvent, PlaySfxEvent, SFX};
-use crate::grid::{CurrentPuzzle, DisplayLevel, GridChanged};
+use crate::grid::{CurrentPuzzle, DisplayLevel, GridChanged, GridUI, GridVeggie, Locked};
+use crate::inventory::DraggedVeg;
 use crate::loading::Textures;
 use crate::overworld::{CurrentSlot, Slot};
 use crate::progress::{get_progress, set_progress};
-use crate::text::{ButtonClick, TextButtonId};
+use crate::puzzle::Puzzle;
+use crate::text::{ButtonClick, ChangeText, TextButtonId};
 use crate::util::Colors;
+use crate::veggie::{Expression, spawn_veggie};
 
 pub struct PlayPlugin;
 
 impl Plugin for PlayPlugin {
     fn build(&self, app: &mut App) {
         app
+            .init_resource::<Moves>()
             .add_system_set(SystemSet::on_enter(GameState::Play)
                 .with_system(play_music)
+                .with_system(reset_moves)
             )
             .add_system_set(SystemSet::on_update(GameState::Play)
                 .with_system(display)
                 .with_system(click_on_button)
                 .with_system(check_finished)
                 .with_system(win_animation)
+                .with_system(grow_animation)
+                .with_system(update_counters)
+                .with_system(check_moves)
             )
             .add_system_set(SystemSet::on_exit(GameState::Play).with_system(cleanup));
     }
@@ -32,6 +41,33 @@ impl Plugin for PlayPlugin {
 #[derive(Component)]
 struct PlayUI;
 
+/// Live score of a scored garden
+#[derive(Component)]
+struct ScoreText;
+
+/// Live count of the sad veggies, in gardens that tolerate some
+#[derive(Component)]
+struct SadText;
+
+/// Live count of the moves, in gardens with a move budget
+#[derive(Component)]
+struct MovesText;
+
+/// Moves made in the current garden: picking up a veggie from the garden or
+/// planting one from the inventory
+#[derive(Resource, Default)]
+pub struct Moves {
+    pub count: usize,
+    /// The budget ran out before the garden was solved
+    failed: bool,
+}
+
+fn reset_moves(
+    mut moves: ResMut<Moves>,
+) {
+    *moves = Moves::default();
+}
+
 fn play_music(
     mut bgm: EventWriter<PlayBgmEvent>,
 ) {
@@ -44,20 +80,24 @@ fn display(
     puzzle: Res<CurrentPuzzle>,
     mut events: EventReader<DisplayLevel>,
     slot: Option<Res<CurrentSlot>>,
+    moves: Res<Moves>,
+    entities: Query<Entity, With<PlayUI>>,
 ) {
     if puzzle.0.is_none() { return; }
     let puzzle = puzzle.0.as_ref().unwrap();
 
     for _ in events.iter() {
+        entities.iter().for_each(|e| commands.entity(e).despawn_recursive());
+
         // Level details
         let x = WIDTH - 32. - 48. - 24.;
-        let h = 7;
-        let y = (HEIGHT - (h + 5) as f32 * 8.) / 2.;
+        let h = 10;
+        let y = (HEIGHT - (h + 4) as f32 * 8.) / 2.;
 
         // Frame
         let id = util::frame(
             &mut commands, &textures,
-            x, y + 5. * 8., util::z::VEG_UI_BG,
+            x, y + 4. * 8., util::z::VEG_UI_BG,
             11, h,
             Colors::DarkRed, Colors::Beige
         );
@@ -74,7 +114,7 @@ fn display(
         let text_x = x + 8.;
         let id = text::spawn_text(
             &mut commands, &textures,
-            Vec3::new(text_x, y + 10. * 8., util::z::VEG_UI),
+            Vec3::new(text_x, y + 12. * 8., util::z::VEG_UI),
             if text.is_empty() { "custom\nlevel" } else { &text },
             Colors::Beige, Colors::DarkRed,
         );
@@ -83,12 +123,21 @@ fn display(
         // Level author
         let id = text::spawn_text(
             &mut commands, &textures,
-            Vec3::new(text_x, y + 7. * 8., util::z::VEG_UI),
+            Vec3::new(text_x, y + 9. * 8., util::z::VEG_UI),
             &format!("by:\n{}", if puzzle.author.is_empty() { "unknown" } else { &puzzle.author }),
             Colors::Beige, Colors::DarkRed,
         );
         commands.entity(id).insert(PlayUI);
 
+        // Rules
+        let id = text::spawn_text(
+            &mut commands, &textures,
+            Vec3::new(text_x, y + 6. * 8., util::z::VEG_UI),
+            &format!("{}{}", puzzle.adjacency.label(), if puzzle.wrap { "\nwrap" } else { "" }),
+            Colors::Beige, Colors::DarkRed,
+        );
+        commands.entity(id).insert(PlayUI);
+
         // Exit button
         let id = util::frame(
             &mut commands, &textures,
@@ -108,17 +157,133 @@ fn display(
             .insert(PlayUI)
             .insert(TextButtonId::LeaveLevel);
 
+        // Boxes below, for the special rules of the garden
+        let mut bottom = y;
+
+        if puzzle.seasons > 0 {
+            let text = grow_text(puzzle.seasons, &format!("goal:  {:0>2}", puzzle.harvest), "- grow! -");
+            let id = spawn_box(&mut commands, &textures, x, &mut bottom, &text);
+            commands.entity(id).insert(TextButtonId::Grow);
+        }
+
+        if puzzle.scored {
+            let id = spawn_box(&mut commands, &textures, x, &mut bottom, &score_text(puzzle));
+            commands.entity(id).insert(ScoreText);
+        }
+
+        if puzzle.sad > 0 {
+            let id = spawn_box(&mut commands, &textures, x, &mut bottom, &sad_text(puzzle));
+            commands.entity(id).insert(SadText);
+        }
+
+        if puzzle.moves > 0 {
+            let id = spawn_box(&mut commands, &textures, x, &mut bottom, &moves_text(puzzle, &moves));
+            commands.entity(id).insert(MovesText);
+        }
+
         break;
     }
     events.clear();
 }
 
+/// Frame with some text below the ones already there, returns the text
+fn spawn_box(commands: &mut Commands, textures: &Res<Textures>, x: f32, bottom: &mut f32, text: &str) -> Entity {
+    let rows = text.lines().count();
+    *bottom -= (rows + 1) as f32 * 8.;
+
+    let id = util::frame(
+        commands, textures,
+        x, *bottom, util::z::VEG_UI_BG,
+        11, rows,
+        Colors::DarkRed, Colors::Beige
+    );
+    commands.entity(id).insert(PlayUI);
+
+    let id = text::spawn_text(
+        commands, textures,
+        Vec3::new(x + 8., *bottom + (rows - 1) as f32 * 8., util::z::VEG_UI),
+        text,
+        Colors::Beige, Colors::DarkRed,
+    );
+    commands.entity(id).insert(PlayUI);
+    id
+}
+
+/// Text of the grow button: the seasons, then the goal or how the growth goes, then the action
+fn grow_text(seasons: u8, status: &str, action: &str) -> String {
+    format!("         \n{} season{}\n{}\n{}\n         ", seasons, if seasons > 1 { "s" } else { " " }, status, action)
+}
+
+fn score_text(puzzle: &Puzzle) -> String {
+    format!("         \nscore  {:0>2}\npar    {:0>2}\n         ", puzzle.score(), puzzle.par)
+}
+
+fn sad_text(puzzle: &Puzzle) -> String {
+    format!(
+        "         \nsad:  {}/{}\n{}\n         ",
+        puzzle.sad_veggies(), puzzle.sad, if puzzle.exact_sad { "exactly  " } else { "at most  " }
+    )
+}
+
+fn moves_text(puzzle: &Puzzle, moves: &Moves) -> String {
+    format!(
+        "         \nmoves  {:0>2}\nlimit  {:0>2}\npar    {}\n         ",
+        moves.count, puzzle.moves, if puzzle.par_moves > 0 { format!("{:0>2}", puzzle.par_moves) } else { "--".to_string() }
+    )
+}
+
+fn update_counters(
+    mut changed: EventReader<GridChanged>,
+    puzzle: Res<CurrentPuzzle>,
+    moves: Res<Moves>,
+    score: Query<Entity, With<ScoreText>>,
+    sad: Query<Entity, With<SadText>>,
+    moves_texts: Query<Entity, With<MovesText>>,
+    mut change_text: EventWriter<ChangeText>,
+) {
+    let changed = changed.iter().count() > 0;
+    if !changed && !moves.is_changed() || puzzle.0.is_none() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+
+    for e in moves_texts.iter() {
+        change_text.send(ChangeText(e, moves_text(puzzle, &moves)));
+    }
+
+    for e in score.iter() {
+        change_text.send(ChangeText(e, score_text(puzzle)));
+    }
+    for e in sad.iter() {
+        change_text.send(ChangeText(e, sad_text(puzzle)));
+    }
+}
+
+/// Seasons played back once the player has planted the garden
+#[derive(Resource)]
+struct Growth {
+    /// Garden as planted by the player, given back for a retry
+    planted: Puzzle,
+    seasons: Vec<Puzzle>,
+    /// Seasons shown so far
+    n: usize,
+    timer: Timer,
+    /// The harvest fell short of the target
+    failed: bool,
+}
+
 fn click_on_button(
+    mut commands: Commands,
     mut clicks: EventReader<ButtonClick>,
     mut state: ResMut<State<GameState>>,
-    block_input: Res<BlockInput>,
+    mut block_input: ResMut<BlockInput>,
+    mut puzzle: ResMut<CurrentPuzzle>,
+    growth: Option<Res<Growth>>,
+    mut moves: ResMut<Moves>,
+    mut display_level: EventWriter<DisplayLevel>,
+    mut sfx: EventWriter<PlaySfxEvent>,
 ) {
-    if block_input.0 { return; }
+    // After a failed harvest or running out of moves, the garden can be replanted or left
+    let failed = growth.as_ref().map_or(false, |g| g.failed) || moves.failed;
+    if block_input.0 && !failed { return; }
 
     for id in clicks.iter() {
         match id.0 {
@@ -126,15 +291,103 @@ fn click_on_button(
                 state.pop().unwrap_or_default();
             }
 
+            TextButtonId::Retry => {
+                if let Some(puzzle) = puzzle.0.as_mut() { puzzle.prepare(); }
+                moves.count = 0;
+                moves.failed = false;
+                block_input.0 = false;
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Grow if failed => {
+                puzzle.0 = Some(growth.as_ref().unwrap().planted.clone());
+                commands.remove_resource::<Growth>();
+                block_input.0 = false;
+                display_level.send(DisplayLevel);
+            }
+
+            TextButtonId::Grow => {
+                if puzzle.0.is_none() { continue; }
+                let planted = puzzle.0.as_ref().unwrap();
+
+                // Everything has to be planted first
+                if planted.veggies.iter().any(|v| planted.remaining_veggie(v.0, false) > 0) {
+                    sfx.send(PlaySfxEvent(SFX::Error));
+                    continue;
+                }
+
+                block_input.0 = true;
+                commands.insert_resource(Growth {
+                    planted: planted.clone(),
+                    seasons: season::seasons(planted),
+                    n: 0,
+                    timer: Timer::from_seconds(1., TimerMode::Repeating),
+                    failed: false,
+                });
+            }
+
             _ => {}
         }
     }
 }
 
+/// Remember the best score of the current level
+fn record_score(slot: &Option<Res<CurrentSlot>>, pkv: &mut PkvStore, score: usize) {
+    if let Some(s) = slot {
+        let mut progress = get_progress(pkv);
+        let best = match s.0 {
+            Slot::Level(n) => progress.best_scores.entry(n).or_insert(0),
+            Slot::Custom(n) => progress.best_custom_scores.entry(n).or_insert(0),
+            _ => return,
+        };
+        if score > *best {
+            *best = score;
+            set_progress(pkv, &progress);
+        }
+    }
+}
+
+/// Remember the fewest moves the current level was solved in
+fn record_moves(slot: &Option<Res<CurrentSlot>>, pkv: &mut PkvStore, moves: usize) {
+    if let Some(s) = slot {
+        let mut progress = get_progress(pkv);
+        let best = match s.0 {
+            Slot::Level(n) => progress.best_moves.entry(n).or_insert(usize::MAX),
+            Slot::Custom(n) => progress.best_custom_moves.entry(n).or_insert(usize::MAX),
+            _ => return,
+        };
+        if moves < *best {
+            *best = moves;
+            set_progress(pkv, &progress);
+        }
+    }
+}
+
+/// Remember the current level as finished
+fn record_win(slot: &Option<Res<CurrentSlot>>, pkv: &mut PkvStore) {
+    if let Some(s) = slot {
+        let mut progress = get_progress(pkv);
+        match s.0 {
+            Slot::Level(n) => { progress.finished_levels.insert(n); },
+            Slot::Custom(n) => { progress.finished_custom.insert(n); },
+            _ => {},
+        };
+        set_progress(pkv, &progress);
+    }
+}
+
+/// Everything is planted and the garden is fine, scored gardens at par.
+/// Growing gardens are judged on their harvest instead.
+fn solved(puzzle: &Puzzle) -> bool {
+    let planted = puzzle.veggies.iter().all(|v| puzzle.remaining_veggie(v.0, false) == 0);
+    puzzle.seasons == 0 && planted && puzzle.is_valid().is_ok() && (!puzzle.scored || puzzle.score() >= puzzle.par)
+}
+
 fn check_finished(
     mut commands: Commands,
     mut changed: EventReader<GridChanged>,
     puzzle: Res<CurrentPuzzle>,
+    moves: Res<Moves>,
     mut block_input: ResMut<BlockInput>,
     slot: Option<Res<CurrentSlot>>,
     mut pkv: ResMut<PkvStore>,
@@ -144,23 +397,155 @@ fn check_finished(
     let puzzle = puzzle.0.as_ref().unwrap();
 
     for _ in changed.iter() {
-        if puzzle.veggies.iter().all(|v| puzzle.remaining_veggie(v.0, false) == 0)
-            && puzzle.is_valid().is_ok() {
+        let planted = puzzle.veggies.iter().all(|v| puzzle.remaining_veggie(v.0, false) == 0);
+        if puzzle.scored && planted {
+            record_score(&slot, pkv.as_mut(), puzzle.score());
+        }
+
+        if solved(puzzle) {
             block_input.0 = true;
             commands.insert_resource(WinAnimation { n: 0, frame: 0, global: 0 });
 
             sfx.send(PlaySfxEvent(SFX::Win));
+            record_win(&slot, pkv.as_mut());
+            if puzzle.moves > 0 { record_moves(&slot, pkv.as_mut(), moves.count); }
+        }
+    }
+}
+
+/// Show the fail popup once the last move didn't solve the garden
+fn check_moves(
+    mut commands: Commands,
+    textures: Res<Textures>,
+    puzzle: Res<CurrentPuzzle>,
+    mut moves: ResMut<Moves>,
+    mut block_input: ResMut<BlockInput>,
+    dragged: Query<(), With<DraggedVeg>>,
+    growth: Option<Res<Growth>>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+) {
+    if puzzle.0.is_none() || moves.failed || growth.is_some() || !dragged.is_empty() { return; }
+    let puzzle = puzzle.0.as_ref().unwrap();
+    if !puzzle.out_of_moves(moves.count) { return; }
+
+    // Growing gardens only need to be planted before they are grown
+    let done = if puzzle.seasons > 0 {
+        puzzle.veggies.iter().all(|v| puzzle.remaining_veggie(v.0, false) == 0)
+    } else {
+        solved(puzzle)
+    };
+    if done { return; }
 
-            if let Some(s) = &slot {
-                let mut progress = get_progress(pkv.as_ref());
-                match s.0 {
-                    Slot::Level(n) => { progress.finished_levels.insert(n); },
-                    Slot::Custom(n) => { progress.finished_custom.insert(n); },
-                    _ => {},
-                };
-                set_progress(pkv.as_mut(), &progress);
+    moves.failed = true;
+    block_input.0 = true;
+    sfx.send(PlaySfxEvent(SFX::Error));
+
+    let (w, h) = (21, 7);
+    let x = ((WIDTH - (w + 2) as f32 * 8.) / 2.).round();
+    let y = ((HEIGHT - (h + 2) as f32 * 8.) / 2.).round();
+    let id = util::frame(
+        &mut commands, &textures,
+        x, y, util::z::POPUP_BG,
+        w, h,
+        Colors::DarkRed, Colors::Beige
+    );
+    commands.entity(id).insert(PlayUI);
+
+    let id = text::spawn_text(
+        &mut commands, &textures,
+        Vec3::new(x + 40., y + 48., util::z::POPUP),
+        "out of moves!",
+        Colors::Beige, Colors::DarkRed,
+    );
+    commands.entity(id).insert(PlayUI);
+
+    for (text_x, text, button) in [(x + 16., "- retry -", TextButtonId::Retry), (x + 96., "- leave -", TextButtonId::LeaveLevel)] {
+        let id = text::spawn_text(
+            &mut commands, &textures,
+            Vec3::new(text_x, y + 16., util::z::POPUP),
+            text,
+            Colors::Beige, Colors::DarkRed,
+        );
+        commands.entity(id)
+            .insert(PlayUI)
+            .insert(button);
+    }
+}
+
+fn grow_animation(
+    mut commands: Commands,
+    time: Res<Time>,
+    textures: Res<Textures>,
+    growth: Option<ResMut<Growth>>,
+    mut puzzle: ResMut<CurrentPuzzle>,
+    veggies: Query<(Entity, &GridVeggie, &Children)>,
+    buttons: Query<(Entity, &TextButtonId)>,
+    mut grid_changed: EventWriter<GridChanged>,
+    mut change_text: EventWriter<ChangeText>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+    slot: Option<Res<CurrentSlot>>,
+    mut pkv: ResMut<PkvStore>,
+) {
+    if growth.is_none() || puzzle.0.is_none() { return; }
+    let mut growth = growth.unwrap();
+    if growth.failed || !growth.timer.tick(time.delta()).just_finished() { return; }
+
+    let seasons = growth.planted.seasons;
+    let button = buttons.iter().find(|(_, id)| **id == TextButtonId::Grow).map(|(e, _)| e);
+
+    if growth.n < growth.seasons.len() {
+        let current = puzzle.0.as_ref().unwrap();
+        let next = growth.seasons[growth.n].clone();
+
+        // Withered veggies fade away
+        for (e, _, faces) in veggies.iter().filter(|(_, v, _)| !next.placed.contains_key(&v.1)) {
+            commands
+                .entity(e)
+                .remove::<GridVeggie>()
+                .insert(Animator::<TextureAtlasSprite>::new(tween::tween_texture_atlas_sprite_opacity(800, false)));
+            for face in faces {
+                commands
+                    .entity(*face)
+                    .insert(Animator::<TextModeTextureAtlasSprite>::new(tween::tween_text_mode_sprite_opacity(800, false)));
             }
         }
+
+        // New veggies sprout on the tiles they grew into
+        for (pos, veggie) in next.placed.iter().filter(|(p, _)| !current.placed.contains_key(*p)) {
+            let tile = grid::get_tile_pos(*pos, &next);
+            let id = spawn_veggie(&mut commands, &textures, tile.extend(util::z::VEGGIE), veggie, Expression::Neutral);
+            commands
+                .entity(id)
+                .insert(GridVeggie(*veggie, *pos, (false, false)))
+                .insert(Locked)
+                .insert(GridUI)
+                .insert(grid::garden_layer())
+                .insert(Animator::<Transform>::new(tween::scale(0., 1., 500)));
+        }
+
+        puzzle.0 = Some(next);
+        growth.n += 1;
+        grid_changed.send(GridChanged);
+        sfx.send(PlaySfxEvent(SFX::Place));
+        if let Some(e) = button {
+            change_text.send(ChangeText(e, grow_text(seasons, &format!("season  {}", growth.n), "growing..")));
+        }
+        return;
+    }
+
+    let harvest = puzzle.0.as_ref().unwrap().placed.len();
+    let target = growth.planted.harvest;
+    if harvest >= target {
+        commands.remove_resource::<Growth>();
+        commands.insert_resource(WinAnimation { n: 0, frame: 0, global: 0 });
+        sfx.send(PlaySfxEvent(SFX::Win));
+        record_win(&slot, pkv.as_mut());
+    } else {
+        growth.failed = true;
+        sfx.send(PlaySfxEvent(SFX::Error));
+        if let Some(e) = button {
+            change_text.send(ChangeText(e, grow_text(seasons, &format!("got {:0>2}/{:0>2}", harvest, target), "- retry -")));
+        }
     }
 }
 
@@ -186,7 +571,7 @@ fn win_animation(
 
     if animation.frame == 0 && animation.n < puzzle.placed.len() {
         let veg = puzzle.placed.iter().nth(animation.n).unwrap();
-        let pos = grid::get_tile_pos(*veg.0, puzzle.size);
+        let pos = grid::get_tile_pos(*veg.0, puzzle);
         commands
             .spawn(SpriteBundle {
                 texture: textures.heart.clone(),
@@ -200,6 +585,7 @@ fn win_animation(
                 1000
             )))
             .insert(Animator::new(tween::tween_sprite_opacity(800, false)))
+            .insert(grid::garden_layer())
             .insert(PlayUI);
     }
 
@@ -222,8 +608,19 @@ fn win_animation(
 fn cleanup(
     mut commands: Commands,
     query: Query<Entity, With<PlayUI>>,
+    growth: Option<Res<Growth>>,
+    moves: Res<Moves>,
+    mut block_input: ResMut<BlockInput>,
 ) {
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
+
+    // Leaving after a failed harvest
+    if growth.is_some() {
+        commands.remove_resource::<Growth>();
+        block_input.0 = false;
+    }
+    // Or after running out of moves
+    if moves.failed { block_input.0 = false; }
 }
\ No newline at end of file
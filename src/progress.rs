@@ -1,4 +1,4 @@
-use bevy::utils::HashSet;
+use bevy::utils::{HashMap, HashSet};
 use bevy_pkv::PkvStore;
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +8,16 @@ pub struct Progress {
     pub tutorial: HashSet<u8>,
     pub custom_levels: Vec<(String, bool)>,
     pub finished_custom: HashSet<usize>,
+    /// Best score reached in the scored levels
+    #[serde(default)]
+    pub best_scores: HashMap<usize, usize>,
+    #[serde(default)]
+    pub best_custom_scores: HashMap<usize, usize>,
+    /// Fewest moves the levels with a move budget were solved in
+    #[serde(default)]
+    pub best_moves: HashMap<usize, usize>,
+    #[serde(default)]
+    pub best_custom_moves: HashMap<usize, usize>,
 }
 
 const KEY: &'static str = "progress";
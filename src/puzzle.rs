@@ -1,14 +1,38 @@
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
 use strum::{EnumIter, IntoEnumIterator};
 
 use crate::veggie::Veggie;
 
+#[derive(Clone)]
 pub struct Puzzle {
     pub author: String,
     pub size: (i8, i8),
     pub veggies: HashMap<Veggie, u8>,
     pub tiles: HashMap<(i8, i8), Tile>,
     pub placed: HashMap<(i8, i8), Veggie>,
+    /// Placed veggies that stay locked on the grid
+    pub givens: HashSet<(i8, i8)>,
+    pub adjacency: Adjacency,
+    /// The garden wraps around at its edges
+    pub wrap: bool,
+    /// Fences on the sides of the tiles, blocking adjacency
+    pub fences: HashSet<((i8, i8), Side)>,
+    /// Seasons the garden grows for once planted, 0 for a still garden
+    pub seasons: u8,
+    /// Veggies to harvest after the last season
+    pub harvest: usize,
+    /// The garden is scored on the crop of its veggies instead of their happiness
+    pub scored: bool,
+    /// Best score of a scored garden
+    pub par: usize,
+    /// Moves allowed to solve the garden, 0 for no limit
+    pub moves: usize,
+    /// Fewest moves to solve a garden with a move budget, 0 if unknown
+    pub par_moves: usize,
+    /// Sad veggies tolerated in the garden
+    pub sad: u8,
+    /// Exactly `sad` veggies have to be sad, not just at most
+    pub exact_sad: bool,
 }
 
 impl Default for Puzzle {
@@ -19,18 +43,37 @@ impl Default for Puzzle {
             veggies: HashMap::new(),
             tiles: HashMap::new(),
             placed: HashMap::new(),
+            givens: HashSet::new(),
+            adjacency: Adjacency::Eight,
+            wrap: false,
+            fences: HashSet::new(),
+            seasons: 0,
+            harvest: 0,
+            scored: false,
+            par: 0,
+            moves: 0,
+            par_moves: 0,
+            sad: 0,
+            exact_sad: false,
         }
     }
 }
 
-pub const MAX_W: i8 = 10;
-pub const MAX_H: i8 = 7;
+pub const MAX_W: i8 = 20;
+pub const MAX_H: i8 = 20;
+
+/// Directions on the grid, north is towards higher y
+pub const NORTH: (i8, i8) = (0, 1);
+pub const SOUTH: (i8, i8) = (0, -1);
+
+/// How far the shadow of a sunflower goes
+pub const SHADE_LENGTH: i8 = 2;
 
 impl Puzzle {
     pub fn remaining_veggie(&self, veggie: &Veggie, in_editor: bool) -> usize {
         if in_editor { return 99; }
         if let Some(count) = self.veggies.get(veggie) {
-            return *count as usize - self.placed.iter().filter(|(_, v)| **v == *veggie).count();
+            return *count as usize - self.placed.iter().filter(|(p, v)| **v == *veggie && !self.givens.contains(*p)).count();
         }
         return 0;
     }
@@ -40,22 +83,348 @@ impl Puzzle {
         if !max_size { return Err("The grid is too large!".to_string()); }
         let min_size = self.size.0 >= 1 && self.size.1 >= 1;
         if !min_size { return Err("The grid is too small!".to_string()); }
-        let happy = self.placed.iter().all(|((x, y), v)| is_happy(v, (*x, *y), &self.tiles, &self.placed) == (true, true));
-        if !happy { return Err("The veggies should be happy!".to_string()); }
+        if self.is_hex() && self.wrap { return Err("Hex gardens can't wrap around!".to_string()); }
+        if self.is_hex() && !self.fences.is_empty() { return Err("Hex gardens can't have fences!".to_string()); }
+        if self.scored && self.seasons > 0 { return Err("Gardens that grow can't be scored!".to_string()); }
+        if self.sad > 0 && (self.scored || self.seasons > 0) { return Err("Only still gardens can tolerate sad veggies!".to_string()); }
+        let mut covered = HashSet::new();
+        let fit = self.placed.iter().all(|(p, v)| (v.span() == 1 || !self.is_hex())
+            && self.footprint(v, *p).into_iter().all(|c| self.is_garden(c) && !self.tiles.contains_key(&c) && covered.insert(c)));
+        if !fit { return Err("The veggies don't fit in the garden!".to_string()); }
+        // Sad veggies are allowed in gardens that grow, they just wither, and in scored gardens
+        if self.seasons == 0 && !self.scored {
+            let sad = self.sad_veggies();
+            if self.exact_sad && sad != self.sad as usize { return Err(format!("Exactly {} veggies should be sad!", self.sad)); }
+            if sad > self.sad as usize && self.sad > 0 { return Err(format!("At most {} veggies can be sad!", self.sad)); }
+            if sad > 0 && self.sad == 0 { return Err("The veggies should be happy!".to_string()); }
+        }
         let one_veg = self.placed.len() > 0;
         if !one_veg { return Err("The puzzle is empty!".to_string()); }
         return Ok(());
     }
 
+    /// Placed veggies that aren't fully happy.
+    pub fn sad_veggies(&self) -> usize {
+        self.placed.iter().filter(|(p, v)| is_happy(v, **p, self) != (true, true)).count()
+    }
+
+    /// The move budget of the garden is spent.
+    pub fn out_of_moves(&self, moves: usize) -> bool {
+        self.moves > 0 && moves >= self.moves
+    }
+
+    /// Every veggie has to be happy in a solution.
+    pub fn strict(&self) -> bool {
+        self.sad == 0 && self.seasons == 0 && !self.scored
+    }
+
+    /// Total crop of the veggies placed in the garden.
+    pub fn score(&self) -> usize {
+        self.placed.iter().map(|(p, v)| crop(v, *p, self)).sum()
+    }
+
     pub fn prepare(&mut self) {
-        self.placed.clear();
+        self.placed.retain(|pos, _| self.givens.contains(pos));
+    }
+
+    /// Tiles of the grid where a veggie could still be planted, row by row.
+    pub fn free_tiles(&self) -> Vec<(i8, i8)> {
+        let mut free = vec![];
+        for y in 0..self.size.1 {
+            for x in 0..self.size.0 {
+                if !self.tiles.contains_key(&(x, y)) && self.occupant((x, y)).is_none() {
+                    free.push((x, y));
+                }
+            }
+        }
+        free
+    }
+
+    /// The tile is part of the garden.
+    pub fn is_garden(&self, pos: (i8, i8)) -> bool {
+        pos.0 >= 0 && pos.1 >= 0 && pos.0 < self.size.0 && pos.1 < self.size.1
+            && self.tiles.get(&pos) != Some(&Tile::Void)
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.adjacency == Adjacency::Hex
+    }
+
+    /// Placed veggie covering the tile, with the tile it is anchored at.
+    pub fn occupant(&self, pos: (i8, i8)) -> Option<((i8, i8), &Veggie)> {
+        if let Some(veggie) = self.placed.get(&pos) { return Some((pos, veggie)); }
+
+        // Big veggies are anchored at their bottom left tile
+        self.placed
+            .iter()
+            .find(|(p, v)| v.span() > 1 && self.footprint(v, **p).contains(&pos))
+            .map(|(p, v)| (*p, v))
+    }
+
+    /// Tiles covered by the veggie anchored at `pos`.
+    pub fn footprint(&self, veggie: &Veggie, pos: (i8, i8)) -> Vec<(i8, i8)> {
+        veggie.footprint().iter().map(|(dx, dy)| (pos.0 + dx, pos.1 + dy)).collect()
+    }
+
+    /// The veggie can be planted at `pos`: its whole footprint is free garden.
+    pub fn fits(&self, veggie: &Veggie, pos: (i8, i8)) -> bool {
+        if veggie.span() > 1 && self.is_hex() { return false; }
+        self.footprint(veggie, pos)
+            .iter()
+            .all(|p| self.is_garden(*p) && !self.tiles.contains_key(p) && self.occupant(*p).is_none())
+    }
+
+    pub fn wrapped(&self, pos: (i8, i8)) -> (i8, i8) {
+        if !self.wrap { return pos; }
+        (pos.0.rem_euclid(self.size.0), pos.1.rem_euclid(self.size.1))
+    }
+
+    pub fn neighbours(&self, pos: (i8, i8)) -> Vec<(i8, i8)> {
+        // On small wrapping gardens, several offsets can lead to the same tile
+        let mut neighbours = vec![];
+        for (dx, dy) in self.adjacency.offsets(pos.1) {
+            let n = self.wrapped((pos.0 + dx, pos.1 + dy));
+            if n != pos && !neighbours.contains(&n) && !self.fenced_off(pos, (dx, dy)) { neighbours.push(n); }
+        }
+        neighbours
+    }
+
+    /// Fence slot between `pos` and the tile one orthogonal `step` away.
+    pub fn edge(&self, pos: (i8, i8), step: (i8, i8)) -> ((i8, i8), Side) {
+        match step {
+            (1, 0) => (pos, Side::East),
+            (-1, 0) => (self.wrapped((pos.0 - 1, pos.1)), Side::East),
+            (0, 1) => (pos, Side::North),
+            _ => (self.wrapped((pos.0, pos.1 - 1)), Side::North),
+        }
+    }
+
+    /// Remove the fences that no longer stand between two tiles of the garden.
+    pub fn trim_fences(&mut self) {
+        let fences = self.fences
+            .iter()
+            .copied()
+            .filter(|(pos, side)| {
+                let step = if *side == Side::East { (1, 0) } else { (0, 1) };
+                self.is_garden(*pos) && self.is_garden(self.wrapped((pos.0 + step.0, pos.1 + step.1)))
+            })
+            .collect();
+        self.fences = fences;
+    }
+
+    /// A fence stands between `pos` and the tile one orthogonal `step` away.
+    pub fn fenced(&self, pos: (i8, i8), step: (i8, i8)) -> bool {
+        !self.fences.is_empty() && self.fences.contains(&self.edge(pos, step))
+    }
+
+    /// Every shortest path from `pos` to the tile `delta` away crosses a fence,
+    /// so a diagonal is only cut by a fence on both ways around the corner.
+    fn fenced_off(&self, pos: (i8, i8), delta: (i8, i8)) -> bool {
+        if self.fences.is_empty() || self.is_hex() || delta == (0, 0) { return false; }
+        [(delta.0.signum(), 0), (0, delta.1.signum())]
+            .into_iter()
+            .filter(|step| *step != (0, 0))
+            .all(|step| self.fenced(pos, step)
+                || self.fenced_off(self.wrapped((pos.0 + step.0, pos.1 + step.1)), (delta.0 - step.0, delta.1 - step.1)))
+    }
+
+    /// Tiles around the footprint of the veggie anchored at `pos`.
+    pub fn around(&self, veggie: &Veggie, pos: (i8, i8)) -> Vec<(i8, i8)> {
+        let footprint = self.footprint(veggie, pos);
+        let mut around = vec![];
+        for n in footprint.iter().flat_map(|p| self.neighbours(*p)) {
+            if !footprint.contains(&n) && !around.contains(&n) { around.push(n); }
+        }
+        around
+    }
+
+    /// Tiles sharing a side with the footprint of the veggie anchored at `pos`.
+    pub fn sides(&self, veggie: &Veggie, pos: (i8, i8)) -> Vec<(i8, i8)> {
+        let footprint = self.footprint(veggie, pos);
+        let mut sides = vec![];
+        for (x, y) in footprint.iter() {
+            for (dx, dy) in Adjacency::Four.offsets(*y) {
+                let n = self.wrapped((x + dx, y + dy));
+                if !footprint.contains(&n) && !sides.contains(&n) && !self.fenced((*x, *y), (dx, dy)) { sides.push(n); }
+            }
+        }
+        sides
     }
+
+    /// Placed veggie that would be too close if `veggie` was planted at `pos`,
+    /// for veggies that need room on their sides.
+    pub fn crowding(&self, veggie: &Veggie, pos: (i8, i8)) -> Option<((i8, i8), &Veggie)> {
+        if needs_room(veggie) {
+            return self.sides(veggie, pos).into_iter().find_map(|p| self.occupant(p));
+        }
+
+        let footprint = self.footprint(veggie, pos);
+        self.placed
+            .iter()
+            .find(|(p, v)| needs_room(v) && self.sides(v, **p).iter().any(|s| footprint.contains(s)))
+            .map(|(p, v)| (*p, v))
+    }
+
+    /// Veggies around the veggie anchored at `pos`, big veggies counted once.
+    pub fn adjacent_veggies(&self, veggie: &Veggie, pos: (i8, i8)) -> Vec<&Veggie> {
+        let mut anchors = vec![];
+        let mut veggies = vec![];
+        for p in self.around(veggie, pos) {
+            if let Some((anchor, v)) = self.occupant(p) {
+                if !anchors.contains(&anchor) {
+                    anchors.push(anchor);
+                    veggies.push(v);
+                }
+            }
+        }
+        veggies
+    }
+
+    /// Channels reached by the water of a source, flowing from channel to channel.
+    pub fn wet_channels(&self) -> HashSet<(i8, i8)> {
+        let mut queue = self.tiles.iter().filter(|(_, t)| **t == Tile::Source).map(|(p, _)| *p).collect::<Vec<_>>();
+        let mut wet = HashSet::new();
+        // Water doesn't flow diagonally, except between hexes
+        let flow = if self.is_hex() { Adjacency::Hex } else { Adjacency::Four };
+        while let Some(pos) = queue.pop() {
+            for (dx, dy) in flow.offsets(pos.1) {
+                let n = self.wrapped((pos.0 + dx, pos.1 + dy));
+                if self.tiles.get(&n) == Some(&Tile::Channel) && wet.insert(n) { queue.push(n); }
+            }
+        }
+        wet
+    }
+
+    /// The veggie anchored at `pos` is next to a source or a wet channel.
+    pub fn is_watered(&self, veggie: &Veggie, pos: (i8, i8)) -> bool {
+        if !self.tiles.values().any(|t| *t == Tile::Source) { return false; }
+        let wet = self.wet_channels();
+        self.around(veggie, pos).iter().any(|p| self.tiles.get(p) == Some(&Tile::Source) || wet.contains(p))
+    }
+
+    /// Garden tiles from 1 up to `distance` steps away from `pos` in `direction`, nearest first.
+    pub fn towards(&self, pos: (i8, i8), direction: (i8, i8), distance: i8) -> Vec<(i8, i8)> {
+        let mut tiles = vec![];
+        for d in 1..=distance {
+            let p = self.wrapped((pos.0 + direction.0 * d, pos.1 + direction.1 * d));
+            if p != pos && self.is_garden(p) && !tiles.contains(&p) { tiles.push(p); }
+        }
+        tiles
+    }
+
+    /// Sunflower casting its shadow on the veggie anchored at `pos`, if any.
+    pub fn shaded_by(&self, veggie: &Veggie, pos: (i8, i8)) -> Option<(i8, i8)> {
+        let footprint = self.footprint(veggie, pos);
+        footprint
+            .iter()
+            .flat_map(|p| self.towards(*p, SOUTH, SHADE_LENGTH))
+            .filter_map(|p| self.occupant(p))
+            .find(|(p, v)| **v == Veggie::Sunflower && !footprint.contains(p))
+            .map(|(p, _)| p)
+    }
+
+    /// Placed veggie needing sun that a sunflower planted at `pos` would shade.
+    pub fn shades(&self, veggie: &Veggie, pos: (i8, i8)) -> Option<((i8, i8), &Veggie)> {
+        if *veggie != Veggie::Sunflower { return None; }
+        self.towards(pos, NORTH, SHADE_LENGTH)
+            .into_iter()
+            .filter_map(|p| self.occupant(p))
+            .find(|(_, v)| needs_sun(v))
+    }
+
+    /// Tiles in the shadow of the placed sunflowers.
+    pub fn shade(&self) -> HashSet<(i8, i8)> {
+        self.placed
+            .iter()
+            .filter(|(_, v)| **v == Veggie::Sunflower)
+            .flat_map(|(p, _)| self.towards(*p, NORTH, SHADE_LENGTH))
+            .collect()
+    }
+
+    /// Both tiles are on the same line or column, or on one of the three axes of a hex garden.
+    /// Lines go all around a wrapping garden, so they stay the same.
+    pub fn in_line(&self, a: (i8, i8), b: (i8, i8)) -> bool {
+        if self.is_hex() {
+            // Axial coordinates of the shifted rows
+            let axial = |(x, y): (i8, i8)| (x - (y - (y & 1)) / 2, y);
+            let (a, b) = (axial(a), axial(b));
+            return a.0 == b.0 || a.1 == b.1 || a.0 + a.1 == b.0 + b.1;
+        }
+        a.0 == b.0 || a.1 == b.1
+    }
+
+    pub fn adjacent<'a, A>(
+        &self,
+        pos: (i8, i8),
+        map: &'a HashMap<(i8, i8), A>,
+    ) -> Vec<&'a A> {
+        self.neighbours(pos)
+            .iter()
+            .filter_map(|p| map.get(p))
+            .collect()
+    }
+}
+
+/// Side of a tile a fence stands on, the other sides belong to the neighbours
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
+pub enum Side {
+    /// Towards higher x
+    East,
+    /// Towards higher y
+    North,
+}
+
+/// Which tiles count as adjacent
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter)]
+pub enum Adjacency {
+    Eight,
+    Four,
+    Knight,
+    /// Hexagonal tiles, odd rows shifted half a tile to the right
+    Hex,
 }
 
-#[derive(Eq, PartialEq, Clone, EnumIter)]
+impl Adjacency {
+    /// Offsets of the neighbours of a tile in the given row
+    pub fn offsets(&self, row: i8) -> Vec<(i8, i8)> {
+        match self {
+            Adjacency::Eight => vec![(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)],
+            Adjacency::Four => vec![(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Adjacency::Knight => vec![(-1, -2), (1, -2), (-2, -1), (2, -1), (-2, 1), (2, 1), (-1, 2), (1, 2)],
+            Adjacency::Hex if row & 1 == 0 => vec![(-1, -1), (0, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)],
+            Adjacency::Hex => vec![(0, -1), (1, -1), (-1, 0), (1, 0), (0, 1), (1, 1)],
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Adjacency::Eight => "diagonals",
+            Adjacency::Four => "no diags",
+            Adjacency::Knight => "knight",
+            Adjacency::Hex => "hex",
+        }
+    }
+
+    pub fn next(&self) -> Adjacency {
+        match self {
+            Adjacency::Eight => Adjacency::Four,
+            Adjacency::Four => Adjacency::Knight,
+            Adjacency::Knight => Adjacency::Hex,
+            Adjacency::Hex => Adjacency::Eight,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, EnumIter)]
 pub enum Tile {
     Water,
     Rock,
+    /// Not part of the garden
+    Void,
+    /// Water flowing into the channels next to it
+    Source,
+    /// Wet when connected to a source, waters the tiles around
+    Channel,
 }
 
 impl Tile {
@@ -63,27 +432,21 @@ impl Tile {
         match self {
             Tile::Water => 2,
             Tile::Rock => 3,
+            Tile::Void => 4,
+            Tile::Source => 5,
+            Tile::Channel => 6,
         }
     }
-}
 
-pub fn adjacent<A>(
-    pos: (i8, i8),
-    map: &HashMap<(i8, i8), A>,
-) -> Vec<&A> {
-    let mut adjacent = vec![];
-
-    for dy in [-1, 0, 1] {
-        for dx in [-1, 0, 1] {
-            if dx == 0 && dy == 0 { continue; }
-
-            if let Some(a) = map.get(&(pos.0 + dx, pos.1 + dy)) {
-                adjacent.push(a);
-            }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tile::Water => "water",
+            Tile::Rock => "rock",
+            Tile::Void => "void",
+            Tile::Source => "source",
+            Tile::Channel => "channel",
         }
     }
-
-    adjacent
 }
 
 pub fn unhappy_adjacent(veggie: &Veggie) -> Vec<Veggie> {
@@ -93,20 +456,58 @@ pub fn unhappy_adjacent(veggie: &Veggie) -> Vec<Veggie> {
     }
 }
 
+/// The two other sisters of corn, beans and squash, which grow best together.
+pub fn sisters(veggie: &Veggie) -> Vec<Veggie> {
+    [Veggie::Corn, Veggie::Bean, Veggie::Squash].into_iter().filter(|v| v != veggie).collect()
+}
+
+/// The veggie is sad in the shadow of a sunflower.
+pub fn needs_sun(veggie: &Veggie) -> bool {
+    matches!(veggie, Veggie::Sunflower | Veggie::Tomato | Veggie::Strawberry | Veggie::Corn)
+}
+
+/// Nothing can be planted along the sides of the veggie.
+pub fn needs_room(veggie: &Veggie) -> bool {
+    *veggie == Veggie::Pumpkin
+}
+
+pub fn hated_tile(veggie: &Veggie) -> Option<Tile> {
+    match veggie {
+        Veggie::Carrot => Some(Tile::Rock),
+        Veggie::Garlic => Some(Tile::Water),
+        _ => None,
+    }
+}
+
+/// What a veggie yields in a scored garden: one, one more for each happy side of its face,
+/// and one per other kind of veggie around it.
+pub fn crop(veggie: &Veggie, pos: (i8, i8), puzzle: &Puzzle) -> usize {
+    let (left, right) = is_happy(veggie, pos, puzzle);
+    let mut companions = puzzle.adjacent_veggies(veggie, pos);
+    companions.retain(|v| *v != veggie);
+    companions.sort_by_key(|v| v.name());
+    companions.dedup();
+    1 + left as usize + right as usize + companions.len()
+}
+
 pub fn is_happy(
     veggie: &Veggie,
     pos: (i8, i8),
-    tiles: &HashMap<(i8, i8), Tile>,
-    veggies: &HashMap<(i8, i8), Veggie>,
+    puzzle: &Puzzle,
 ) -> (bool, bool) {
-    let adjacent_veggies = adjacent(pos, veggies);
-    let adjacent_tiles = adjacent(pos, tiles);
+    let veggies = &puzzle.placed;
+    let around = puzzle.around(veggie, pos);
+    let adjacent_veggies = puzzle.adjacent_veggies(veggie, pos);
+    let adjacent_tiles = around.iter().filter_map(|p| puzzle.tiles.get(p)).collect::<Vec<_>>();
 
     // Unhappy caused by an adjacent veggie
     for impossible in unhappy_adjacent(veggie) {
         if adjacent_veggies.contains(&&impossible) { return (false, false); }
     }
 
+    // In the shadow of a sunflower
+    if needs_sun(veggie) && puzzle.shaded_by(veggie, pos).is_some() { return (false, false); }
+
     // Veggie specific conditions
     match veggie {
         Veggie::Strawberry => {
@@ -117,56 +518,76 @@ pub fn is_happy(
             || adjacent_veggies.contains(&&Veggie::Carrot), true)
         }
         Veggie::Carrot => {
-            (!adjacent_tiles.contains(&&Tile::Rock), true)
+            (!adjacent_tiles.contains(&&hated_tile(veggie).unwrap()), true)
         }
         Veggie::Cherry => {
             // Exactly one adjacent cherry
             let c1 = adjacent_veggies.iter().filter(|&&v| v == &Veggie::Cherry).count() == 1;
             // No apples in the line / column
-            let c2 = veggies.iter().filter(|(&(x, y), v)| v == &&Veggie::Apple && (x == pos.0 || y == pos.1)).count() == 0;
+            let c2 = veggies.iter().filter(|(&p, v)| v == &&Veggie::Apple && puzzle.in_line(p, pos)).count() == 0;
             (c1, c2)
         }
         Veggie::Garlic => {
-            (!adjacent_tiles.contains(&&Tile::Water), true)
+            (!adjacent_tiles.contains(&&hated_tile(veggie).unwrap()) && !puzzle.is_watered(veggie, pos), true)
+        }
+        Veggie::Celery => {
+            (puzzle.is_watered(veggie, pos), true)
+        }
+        Veggie::Corn | Veggie::Bean | Veggie::Squash => {
+            // Needs both other sisters around
+            (sisters(veggie).iter().all(|s| adjacent_veggies.contains(&s)), true)
+        }
+        Veggie::Pumpkin => {
+            // Needs room: nothing along its sides
+            (puzzle.sides(veggie, pos).iter().all(|p| puzzle.occupant(*p).is_none()), true)
         }
         _ => { (true, true) }
     }
 }
 
+#[cfg(test)]
+fn garden(tiles: HashMap<(i8, i8), Tile>, placed: HashMap<(i8, i8), Veggie>) -> Puzzle {
+    Puzzle { tiles, placed, ..Default::default() }
+}
+
 #[test]
 fn test_strawberry() {
     let tiles = HashMap::from([]);
     let veggies = HashMap::from([((0, 0), Veggie::Strawberry)]);
+    let puzzle = garden(tiles, veggies);
 
-    assert_eq!(is_happy(&Veggie::Strawberry, (1, 0), &tiles, &veggies), (true, true));
-    assert_eq!(is_happy(&Veggie::Strawberry, (0, 2), &tiles, &veggies), (false, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (0, 2), &puzzle), (false, true));
 }
 
 #[test]
 fn test_carrot() {
     let tiles = HashMap::from([((0, 0), Tile::Rock)]);
     let veggies = HashMap::from([]);
+    let puzzle = garden(tiles, veggies);
 
-    assert_eq!(is_happy(&Veggie::Carrot, (0, 1), &tiles, &veggies), (false, true));
-    assert_eq!(is_happy(&Veggie::Carrot, (0, 2), &tiles, &veggies), (true, true));
+    assert_eq!(is_happy(&Veggie::Carrot, (0, 1), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Carrot, (0, 2), &puzzle), (true, true));
 }
 
 #[test]
 fn test_garlic() {
     let tiles = HashMap::from([((0, 0), Tile::Water)]);
     let veggies = HashMap::from([]);
+    let puzzle = garden(tiles, veggies);
 
-    assert_eq!(is_happy(&Veggie::Garlic, (0, 1), &tiles, &veggies), (false, true));
-    assert_eq!(is_happy(&Veggie::Garlic, (0, 2), &tiles, &veggies), (true, true));
+    assert_eq!(is_happy(&Veggie::Garlic, (0, 1), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Garlic, (0, 2), &puzzle), (true, true));
 }
 
 #[test]
 fn test_apple() {
     let tiles = HashMap::from([]);
     let veggies = HashMap::from([((1, 1), Veggie::Apple)]);
+    let puzzle = garden(tiles, veggies);
 
     for veggie in Veggie::iter() {
-        assert_eq!(is_happy(&veggie, (2, 1), &tiles, &veggies), (false, false));
+        assert_eq!(is_happy(&veggie, (2, 1), &puzzle), (false, false));
     }
 }
 
@@ -174,10 +595,11 @@ fn test_apple() {
 fn test_mint() {
     let tiles = HashMap::from([]);
     let veggies = HashMap::from([((1, 1), Veggie::Mint)]);
+    let puzzle = garden(tiles, veggies);
 
     for veggie in [Veggie::Carrot, Veggie::Garlic] {
-        assert_eq!(is_happy(&veggie, (2, 1), &tiles, &veggies), (false, false));
-        assert_eq!(is_happy(&veggie, (3, 1), &tiles, &veggies), (true, true));
+        assert_eq!(is_happy(&veggie, (2, 1), &puzzle), (false, false));
+        assert_eq!(is_happy(&veggie, (3, 1), &puzzle), (true, true));
     }
 }
 
@@ -185,20 +607,248 @@ fn test_mint() {
 fn test_tomato() {
     let tiles = HashMap::from([]);
     let veggies = HashMap::from([((0, 0), Veggie::Garlic), ((5, 0), Veggie::Carrot)]);
+    let puzzle = garden(tiles, veggies);
 
-    assert_eq!(is_happy(&Veggie::Tomato, (0, 5), &tiles, &veggies), (false, true));
-    assert_eq!(is_happy(&Veggie::Tomato, (1, 0), &tiles, &veggies), (true, true));
-    assert_eq!(is_happy(&Veggie::Tomato, (6, 0), &tiles, &veggies), (true, true));
+    assert_eq!(is_happy(&Veggie::Tomato, (0, 5), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Tomato, (1, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Tomato, (6, 0), &puzzle), (true, true));
 }
 
 #[test]
 pub fn test_cherry() {
     let tiles = HashMap::from([]);
     let veggies = HashMap::from([((0, 0), Veggie::Apple), ((3, 1), Veggie::Cherry), ((1, 3), Veggie::Cherry)]);
+    let puzzle = garden(tiles, veggies);
+
+    assert_eq!(is_happy(&Veggie::Cherry, (2, 1), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Cherry, (3, 3), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Cherry, (3, 0), &puzzle), (true, false));
+    assert_eq!(is_happy(&Veggie::Cherry, (0, 3), &puzzle), (true, false));
+    assert_eq!(is_happy(&Veggie::Cherry, (0, 5), &puzzle), (false, false));
+}
+
+#[test]
+fn test_givens() {
+    let mut puzzle = Puzzle::default();
+    puzzle.veggies.insert(Veggie::Strawberry, 1);
+    puzzle.placed.insert((0, 0), Veggie::Strawberry);
+    puzzle.placed.insert((1, 0), Veggie::Strawberry);
+    puzzle.givens.insert((0, 0));
+
+    puzzle.prepare();
+
+    assert_eq!(puzzle.placed.get(&(0, 0)), Some(&Veggie::Strawberry));
+    assert_eq!(puzzle.placed.get(&(1, 0)), None);
+    assert_eq!(puzzle.remaining_veggie(&Veggie::Strawberry, false), 1);
+}
+
+#[test]
+fn test_adjacency() {
+    let tiles = HashMap::from([]);
+    let veggies = HashMap::from([((0, 0), Veggie::Strawberry)]);
+    let mut puzzle = garden(tiles, veggies);
+
+    puzzle.adjacency = Adjacency::Four;
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 1), &puzzle), (false, true));
+
+    puzzle.adjacency = Adjacency::Knight;
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 0), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 2), &puzzle), (true, true));
+}
+
+#[test]
+fn test_void() {
+    let mut puzzle = Puzzle::default();
+    puzzle.tiles.insert((1, 1), Tile::Void);
+
+    assert!(puzzle.is_garden((0, 0)));
+    assert!(!puzzle.is_garden((1, 1)));
+    assert!(!puzzle.is_garden((5, 0)));
+    assert!(!puzzle.free_tiles().contains(&(1, 1)));
+}
+
+#[test]
+fn test_wrap() {
+    let tiles = HashMap::from([((4, 2), Tile::Water)]);
+    let veggies = HashMap::from([((0, 0), Veggie::Cherry), ((4, 0), Veggie::Cherry)]);
+    let mut puzzle = garden(tiles, veggies);
+
+    assert_eq!(is_happy(&Veggie::Cherry, (0, 0), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Garlic, (0, 0), &puzzle), (true, true));
+
+    puzzle.wrap = true;
+    assert_eq!(is_happy(&Veggie::Cherry, (0, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Garlic, (0, 0), &puzzle), (false, true));
 
-    assert_eq!(is_happy(&Veggie::Cherry, (2, 1), &tiles, &veggies), (true, true));
-    assert_eq!(is_happy(&Veggie::Cherry, (3, 3), &tiles, &veggies), (false, true));
-    assert_eq!(is_happy(&Veggie::Cherry, (3, 0), &tiles, &veggies), (true, false));
-    assert_eq!(is_happy(&Veggie::Cherry, (0, 3), &tiles, &veggies), (true, false));
-    assert_eq!(is_happy(&Veggie::Cherry, (0, 5), &tiles, &veggies), (false, false));
-}
\ No newline at end of file
+    // Offsets wrapping to the same tile count once
+    puzzle.size = (2, 1);
+    assert_eq!(puzzle.neighbours((0, 0)), vec![(1, 0)]);
+}
+
+#[test]
+fn test_hex() {
+    let tiles = HashMap::from([]);
+    let veggies = HashMap::from([((1, 1), Veggie::Strawberry), ((0, 3), Veggie::Apple)]);
+    let mut puzzle = garden(tiles, veggies);
+    puzzle.size = (4, 4);
+    puzzle.adjacency = Adjacency::Hex;
+
+    // Odd rows are shifted to the right
+    assert_eq!(is_happy(&Veggie::Strawberry, (2, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (0, 0), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (2, 2), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Strawberry, (3, 2), &puzzle), (false, true));
+
+    // Lines follow the three axes
+    assert_eq!(is_happy(&Veggie::Cherry, (3, 3), &puzzle).1, false);
+    assert_eq!(is_happy(&Veggie::Cherry, (2, 0), &puzzle).1, false);
+    assert_eq!(is_happy(&Veggie::Cherry, (0, 0), &puzzle).1, true);
+    assert_eq!(is_happy(&Veggie::Cherry, (2, 2), &puzzle).1, true);
+}
+
+#[test]
+fn test_pumpkin() {
+    let tiles = HashMap::from([((4, 0), Tile::Rock)]);
+    let veggies = HashMap::from([((1, 1), Veggie::Pumpkin), ((3, 3), Veggie::Strawberry), ((4, 3), Veggie::Strawberry)]);
+    let mut puzzle = garden(tiles, veggies);
+    puzzle.size = (5, 4);
+
+    // Covers a 2x2 area anchored at its bottom left tile
+    assert_eq!(puzzle.occupant((2, 2)).map(|(p, _)| p), Some((1, 1)));
+    assert!(!puzzle.free_tiles().contains(&(2, 1)));
+    assert!(!puzzle.fits(&Veggie::Pumpkin, (3, 0)));
+    assert!(puzzle.fits(&Veggie::Pumpkin, (3, 1)));
+
+    // Diagonal neighbours don't take its room
+    assert_eq!(is_happy(&Veggie::Pumpkin, (1, 1), &puzzle), (true, true));
+    assert!(puzzle.is_valid().is_ok());
+
+    // Strawberries next to it still see it once
+    puzzle.placed.insert((3, 2), Veggie::Strawberry);
+    assert_eq!(is_happy(&Veggie::Pumpkin, (1, 1), &puzzle), (false, true));
+    assert_eq!(puzzle.adjacent_veggies(&Veggie::Strawberry, (3, 1)).len(), 2);
+    assert!(puzzle.is_valid().is_err());
+}
+
+#[test]
+fn test_three_sisters() {
+    let tiles = HashMap::from([]);
+    let veggies = HashMap::from([((0, 0), Veggie::Corn), ((1, 0), Veggie::Bean), ((3, 0), Veggie::Squash)]);
+    let mut puzzle = garden(tiles, veggies);
+
+    assert_eq!(is_happy(&Veggie::Corn, (0, 0), &puzzle), (false, true));
+    assert_eq!(is_happy(&Veggie::Bean, (1, 0), &puzzle), (false, true));
+
+    // A single squash between the two is enough
+    puzzle.placed.remove(&(3, 0));
+    puzzle.placed.insert((0, 1), Veggie::Squash);
+    assert_eq!(is_happy(&Veggie::Corn, (0, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Bean, (1, 0), &puzzle), (true, true));
+    assert_eq!(is_happy(&Veggie::Squash, (0, 1), &puzzle), (true, true));
+}
+
+#[test]
+fn test_sunflower() {
+    let tiles = HashMap::from([((1, 2), Tile::Rock)]);
+    let veggies = HashMap::from([((1, 0), Veggie::Sunflower)]);
+    let mut puzzle = garden(tiles, veggies);
+    puzzle.size = (3, 4);
+
+    assert_eq!(puzzle.towards((1, 0), NORTH, SHADE_LENGTH), vec![(1, 1), (1, 2)]);
+    assert_eq!(puzzle.shade().len(), 2);
+
+    // Sun lovers are sad in its shadow, others don't mind
+    assert_eq!(is_happy(&Veggie::Sunflower, (1, 0), &puzzle), (true, true));
+    assert_eq!(puzzle.shaded_by(&Veggie::Tomato, (1, 2)), Some((1, 0)));
+    assert_eq!(is_happy(&Veggie::Sunflower, (1, 1), &puzzle), (false, false));
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 3), &puzzle), (false, true));
+    assert_eq!(puzzle.shaded_by(&Veggie::Strawberry, (1, 3)), None);
+    assert_eq!(is_happy(&Veggie::Garlic, (1, 1), &puzzle), (true, true));
+
+    // Shadows go around a wrapping garden
+    puzzle.wrap = true;
+    puzzle.placed.insert((0, 3), Veggie::Sunflower);
+    assert_eq!(is_happy(&Veggie::Sunflower, (0, 3), &puzzle), (true, true));
+    assert!(puzzle.shade().contains(&(0, 0)));
+    assert_eq!(puzzle.shades(&Veggie::Sunflower, (1, 3)), Some(((1, 0), &Veggie::Sunflower)));
+}
+
+#[test]
+fn test_fences() {
+    let tiles = HashMap::from([]);
+    let veggies = HashMap::from([((1, 1), Veggie::Strawberry), ((2, 1), Veggie::Strawberry)]);
+    let mut puzzle = garden(tiles, veggies);
+
+    // Fences between two tiles keep them apart
+    puzzle.fences.insert(((1, 1), Side::East));
+    assert!(puzzle.fenced((2, 1), (-1, 0)));
+    assert_eq!(is_happy(&Veggie::Strawberry, (1, 1), &puzzle), (false, true));
+
+    // Diagonals need a fence on both ways around the corner
+    puzzle.placed.insert((2, 2), Veggie::Apple);
+    assert_eq!(is_happy(&Veggie::Apple, (2, 2), &puzzle), (true, true));
+    assert!(puzzle.neighbours((1, 1)).contains(&(2, 2)));
+    puzzle.fences.insert(((1, 1), Side::North));
+    assert!(!puzzle.neighbours((1, 1)).contains(&(2, 2)));
+    assert!(puzzle.neighbours((1, 1)).contains(&(0, 2)));
+}
+
+#[test]
+fn test_irrigation() {
+    let tiles = HashMap::from([
+        ((0, 0), Tile::Source),
+        ((1, 0), Tile::Channel),
+        ((2, 0), Tile::Channel),
+        ((4, 0), Tile::Channel),
+    ]);
+    let mut puzzle = garden(tiles, HashMap::new());
+
+    // Water doesn't jump over the gap
+    assert_eq!(puzzle.wet_channels().len(), 2);
+    assert!(is_happy(&Veggie::Celery, (3, 1), &puzzle).0);
+    assert!(!is_happy(&Veggie::Celery, (4, 1), &puzzle).0);
+    assert!(!is_happy(&Veggie::Garlic, (1, 1), &puzzle).0);
+    assert!(is_happy(&Veggie::Garlic, (4, 2), &puzzle).0);
+
+    puzzle.tiles.insert((3, 0), Tile::Channel);
+    assert!(is_happy(&Veggie::Celery, (4, 1), &puzzle).0);
+}
+
+#[test]
+fn test_crop() {
+    let veggies = HashMap::from([
+        ((0, 0), Veggie::Tomato),
+        ((1, 0), Veggie::Garlic),
+        ((2, 0), Veggie::Carrot),
+    ]);
+    let puzzle = garden(HashMap::from([((4, 0), Tile::Rock)]), veggies);
+
+    // Happy, with one or two other kinds of veggies around
+    assert_eq!(crop(&Veggie::Tomato, (0, 0), &puzzle), 4);
+    assert_eq!(crop(&Veggie::Garlic, (1, 0), &puzzle), 5);
+    assert_eq!(crop(&Veggie::Carrot, (2, 0), &puzzle), 4);
+    assert_eq!(puzzle.score(), 13);
+
+    // Half sad next to the rock, and carrots aren't companions of carrots
+    let mut puzzle = puzzle;
+    puzzle.placed.insert((3, 0), Veggie::Carrot);
+    assert_eq!(crop(&Veggie::Carrot, (3, 0), &puzzle), 2);
+}
+
+#[test]
+fn test_tolerance() {
+    let veggies = HashMap::from([((0, 0), Veggie::Strawberry), ((2, 0), Veggie::Strawberry)]);
+    let mut puzzle = garden(HashMap::new(), veggies);
+    assert_eq!(puzzle.sad_veggies(), 2);
+    assert!(puzzle.is_valid().is_err());
+
+    puzzle.sad = 2;
+    assert!(puzzle.is_valid().is_ok());
+
+    puzzle.sad = 3;
+    puzzle.exact_sad = true;
+    assert!(puzzle.is_valid().is_err());
+    puzzle.exact_sad = false;
+    assert!(puzzle.is_valid().is_ok());
+}
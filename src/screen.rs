@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::{WindowMode, WindowResized, WindowScaleFactorChanged};
+use bevy_pkv::PkvStore;
+use serde::{Deserialize, Serialize};
+use strum::EnumIter;
+
+use crate::{HEIGHT, WIDTH};
+use crate::input::{Action, Actions};
+use crate::settings::{set_settings, Settings};
+
+pub struct ScreenPlugin;
+
+impl Plugin for ScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_system(toggle_fullscreen)
+            .add_system(apply_window_mode.after(toggle_fullscreen))
+            .add_system(fit_cameras.after(toggle_fullscreen));
+    }
+}
+
+/// How the game is scaled up to fill the window
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, EnumIter)]
+pub enum ScaleMode {
+    /// Largest whole scale that fits, so that all pixels have the same size
+    #[default]
+    Integer,
+    /// Largest scale that fits
+    Fit,
+}
+
+impl ScaleMode {
+    /// Position and size of the game in a window of `window` physical pixels,
+    /// centered with bars around it.
+    pub fn letterbox(&self, window: UVec2) -> (UVec2, UVec2) {
+        let fit = (window.x as f32 / WIDTH).min(window.y as f32 / HEIGHT);
+        // Windows smaller than the game are filled anyway
+        let scale = match self {
+            ScaleMode::Integer if fit >= 1. => fit.floor(),
+            _ => fit,
+        };
+        let size = Vec2::new(WIDTH * scale, HEIGHT * scale).round().as_uvec2().min(window);
+        ((window - size) / 2, size)
+    }
+}
+
+/// F11 switches between fullscreen and windowed
+fn toggle_fullscreen(
+    actions: Res<Actions>,
+    mut settings: ResMut<Settings>,
+    mut pkv: ResMut<PkvStore>,
+) {
+    if actions.just_pressed(Action::Fullscreen) {
+        settings.fullscreen = !settings.fullscreen;
+        set_settings(pkv.as_mut(), &settings);
+    }
+}
+
+fn apply_window_mode(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+) {
+    if !settings.is_changed() { return; }
+    if let Some(window) = windows.get_primary_mut() {
+        let mode = if settings.fullscreen { WindowMode::BorderlessFullscreen } else { WindowMode::Windowed };
+        if window.mode() != mode { window.set_mode(mode); }
+    }
+}
+
+/// Both cameras draw the game in the same letterboxed viewport
+fn fit_cameras(
+    mut resized: EventReader<WindowResized>,
+    mut rescaled: EventReader<WindowScaleFactorChanged>,
+    settings: Res<Settings>,
+    windows: Res<Windows>,
+    mut cameras: Query<&mut Camera>,
+) {
+    let changed = resized.iter().count() + rescaled.iter().count() > 0;
+    if !changed && !settings.is_changed() { return; }
+
+    if let Some(window) = windows.get_primary() {
+        // Minimized
+        if window.physical_width() == 0 || window.physical_height() == 0 { return; }
+
+        let (position, size) = settings.scale.letterbox(UVec2::new(window.physical_width(), window.physical_height()));
+        for mut camera in cameras.iter_mut() {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: size,
+                ..default()
+            });
+        }
+    }
+}
+
+#[test]
+fn test_letterbox() {
+    assert_eq!(ScaleMode::Integer.letterbox(UVec2::new(1280, 720)), (UVec2::new(0, 0), UVec2::new(1280, 720)));
+    assert_eq!(ScaleMode::Integer.letterbox(UVec2::new(1800, 1200)), (UVec2::new(260, 240), UVec2::new(1280, 720)));
+    assert_eq!(ScaleMode::Fit.letterbox(UVec2::new(1920, 1200)), (UVec2::new(0, 60), UVec2::new(1920, 1080)));
+    assert_eq!(ScaleMode::Integer.letterbox(UVec2::new(2560, 1440)), (UVec2::new(0, 0), UVec2::new(2560, 1440)));
+    // Too small for a whole scale
+    assert_eq!(ScaleMode::Integer.letterbox(UVec2::new(320, 360)), (UVec2::new(0, 90), UVec2::new(320, 180)));
+}
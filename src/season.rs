@@ -0,0 +1,108 @@
+use bevy::utils::HashSet;
+
+use crate::puzzle;
+use crate::puzzle::Puzzle;
+use crate::veggie::Veggie;
+
+/// Most seasons a garden can grow for
+pub const MAX_SEASONS: u8 = 4;
+
+/// The garden one season later: unhappy veggies wither, happy strawberries spread
+/// to every free tile around them and happy mint creeps into the first one left.
+/// New veggies don't come from the inventory, they are givens.
+pub fn grow(puzzle: &Puzzle) -> Puzzle {
+    let mut next = puzzle.clone();
+    let mut alive = vec![];
+    for (pos, veggie) in puzzle.placed.iter() {
+        if puzzle::is_happy(veggie, *pos, puzzle) == (true, true) {
+            alive.push((*pos, *veggie));
+        } else {
+            next.placed.remove(pos);
+            next.givens.remove(pos);
+        }
+    }
+
+    // Only tiles free at the start of the season can be grown into
+    let free = |p: &(i8, i8)| puzzle.is_garden(*p) && !puzzle.tiles.contains_key(p) && puzzle.occupant(*p).is_none();
+
+    let mut strawberries = HashSet::new();
+    for (pos, _) in alive.iter().filter(|(_, v)| *v == Veggie::Strawberry) {
+        strawberries.extend(puzzle.neighbours(*pos).into_iter().filter(|p| free(p)));
+    }
+
+    let mut mint = HashSet::new();
+    for (pos, _) in alive.iter().filter(|(_, v)| *v == Veggie::Mint) {
+        if let Some(p) = puzzle.neighbours(*pos).into_iter().find(|p| free(p) && !strawberries.contains(p)) {
+            mint.insert(p);
+        }
+    }
+
+    for (sprouts, veggie) in [(strawberries, Veggie::Strawberry), (mint, Veggie::Mint)] {
+        for p in sprouts {
+            next.placed.insert(p, veggie);
+            next.givens.insert(p);
+        }
+    }
+
+    next
+}
+
+/// The garden at the end of each of its seasons.
+pub fn seasons(puzzle: &Puzzle) -> Vec<Puzzle> {
+    let mut seasons: Vec<Puzzle> = vec![];
+    for _ in 0..puzzle.seasons {
+        let next = grow(seasons.last().unwrap_or(puzzle));
+        seasons.push(next);
+    }
+    seasons
+}
+
+/// Veggies left in the garden after the last season.
+pub fn harvest(puzzle: &Puzzle) -> usize {
+    seasons(puzzle).last().unwrap_or(puzzle).placed.len()
+}
+
+#[test]
+fn test_grow() {
+    use bevy::utils::HashMap;
+    use crate::puzzle::Tile;
+
+    let mut puzzle = Puzzle::default();
+    puzzle.tiles = HashMap::from([((3, 2), Tile::Rock)]);
+    puzzle.placed = HashMap::from([
+        ((0, 0), Veggie::Strawberry),
+        ((1, 0), Veggie::Strawberry),
+        ((4, 0), Veggie::Mint),
+        ((4, 2), Veggie::Carrot),
+    ]);
+
+    let next = grow(&puzzle);
+    // The carrot next to the rock withers
+    assert_eq!(next.placed.get(&(4, 2)), None);
+    for p in [(0, 1), (1, 1), (2, 0), (2, 1)] {
+        assert_eq!(next.placed.get(&p), Some(&Veggie::Strawberry));
+        assert!(next.givens.contains(&p));
+    }
+    assert_eq!(next.placed.get(&(3, 0)), Some(&Veggie::Mint));
+    assert_eq!(next.placed.len(), 8);
+
+    // Same garden, same seasons
+    assert_eq!(grow(&puzzle).placed, next.placed);
+}
+
+#[test]
+fn test_harvest() {
+    use bevy::utils::HashMap;
+
+    let mut puzzle = Puzzle::default();
+    puzzle.placed = HashMap::from([((0, 0), Veggie::Strawberry), ((1, 0), Veggie::Strawberry)]);
+    assert_eq!(harvest(&puzzle), 2);
+
+    puzzle.seasons = 1;
+    assert_eq!(harvest(&puzzle), 6);
+
+    // The whole garden gets invaded
+    puzzle.seasons = 3;
+    assert_eq!(harvest(&puzzle), 15);
+    assert_eq!(seasons(&puzzle).len(), 3);
+}
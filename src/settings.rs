@@ -0,0 +1,457 @@
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy_pkv::PkvStore;
+use bevy_text_mode::TextModeTextureAtlasSprite;
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
+
+use crate::{GameState, HEIGHT, text, util, WIDTH};
+use crate::loading::Textures;
+use crate::screen::ScaleMode;
+use crate::text::{ButtonClick, ChangeText, TextButtonId};
+use crate::util::{Colors, Palette};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Settings>()
+            .add_startup_system(load_settings)
+            .add_system(apply_palette)
+            .add_system_set(SystemSet::on_enter(GameState::Settings).with_system(setup))
+            .add_system_set(SystemSet::on_update(GameState::Settings)
+                .with_system(click_on_button)
+                .with_system(update_texts.after(click_on_button))
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(cleanup));
+    }
+}
+
+/// Preferences of the player, saved next to the progress
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Volume of the music, from 0 to `MAX_VOLUME`
+    pub music: u8,
+    /// Volume of the sound effects, from 0 to `MAX_VOLUME`
+    pub sounds: u8,
+    pub mute: bool,
+    /// How the game is scaled up to fill the window
+    pub scale: ScaleMode,
+    pub fullscreen: bool,
+    pub palette: Palette,
+    pub language: Language,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            music: 8,
+            sounds: 8,
+            mute: false,
+            scale: ScaleMode::default(),
+            fullscreen: false,
+            palette: Palette::default(),
+            language: Language::default(),
+        }
+    }
+}
+
+pub const MAX_VOLUME: u8 = 10;
+
+impl Settings {
+    /// Volume of the music channel, 0.4 with the default settings
+    pub fn bgm_volume(&self) -> f64 {
+        if self.mute { 0. } else { 0.5 * self.music as f64 / MAX_VOLUME as f64 }
+    }
+
+    /// Volume of the sound effects channel, 0.3 with the default settings
+    pub fn sfx_volume(&self) -> f64 {
+        if self.mute { 0. } else { 0.375 * self.sounds as f64 / MAX_VOLUME as f64 }
+    }
+
+    /// Sliders move by `step`, the other settings go to their next or previous value
+    pub fn change(&mut self, setting: Setting, step: i8) {
+        let slide = |volume: u8| (volume as i8 + step).clamp(0, MAX_VOLUME as i8) as u8;
+        match setting {
+            Setting::Music => self.music = slide(self.music),
+            Setting::Sounds => self.sounds = slide(self.sounds),
+            Setting::Mute => self.mute = !self.mute,
+            Setting::Scale => self.scale = cycle(self.scale, step),
+            Setting::Fullscreen => self.fullscreen = !self.fullscreen,
+            Setting::Palette => self.palette = cycle(self.palette, step),
+            Setting::Language => self.language = cycle(self.language, step),
+        }
+    }
+
+    fn value(&self, setting: Setting) -> String {
+        let bar = |volume: u8| "=".repeat(volume as usize) + &"-".repeat((MAX_VOLUME - volume) as usize);
+        let on_off = |on: bool| if on { Label::On } else { Label::Off };
+        let value = match setting {
+            Setting::Music => return bar(self.music),
+            Setting::Sounds => return bar(self.sounds),
+            Setting::Mute => on_off(self.mute),
+            Setting::Scale => match self.scale {
+                ScaleMode::Integer => Label::Whole,
+                ScaleMode::Fit => Label::Fit,
+            },
+            Setting::Fullscreen => on_off(self.fullscreen),
+            Setting::Palette => match self.palette {
+                Palette::Pico8 => Label::Pico8,
+                Palette::Contrast => Label::Contrast,
+                Palette::Colorblind => Label::Colorblind,
+            },
+            Setting::Language => match self.language {
+                Language::English => Label::English,
+                Language::French => Label::French,
+            },
+        };
+        self.language.translate(value).to_string()
+    }
+}
+
+/// Next or previous value of an enum, wrapping around
+fn cycle<T: IntoEnumIterator + PartialEq + Copy>(value: T, step: i8) -> T {
+    let values = T::iter().collect::<Vec<T>>();
+    let i = values.iter().position(|v| *v == value).unwrap_or(0) as isize;
+    values[(i + step as isize).rem_euclid(values.len() as isize) as usize]
+}
+
+/// Language of the menus, the levels and the tutorial stay in English
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, EnumIter)]
+pub enum Language {
+    #[default]
+    English,
+    French,
+}
+
+impl Language {
+    pub fn translate(&self, label: Label) -> &'static str {
+        let (english, french) = match label {
+            Label::Settings => ("settings", "parametres"),
+            Label::Music => ("music", "musique"),
+            Label::Sounds => ("sounds", "sons"),
+            Label::Mute => ("mute", "muet"),
+            Label::Scale => ("scale", "echelle"),
+            Label::Fullscreen => ("fullscreen", "plein ecran"),
+            Label::Palette => ("palette", "palette"),
+            Label::Language => ("language", "langue"),
+            Label::On => ("on", "oui"),
+            Label::Off => ("off", "non"),
+            Label::Whole => ("whole", "entiere"),
+            Label::Fit => ("fit", "ajustee"),
+            Label::Pico8 => ("pico-8", "pico-8"),
+            Label::Contrast => ("contrast", "contraste"),
+            Label::Colorblind => ("colorblind", "daltonien"),
+            Label::English => ("english", "english"),
+            Label::French => ("francais", "francais"),
+            Label::Back => ("- back -", "- retour -"),
+            Label::Paused => ("paused", "pause"),
+            Label::Resume => ("- resume -", "- reprendre -"),
+            Label::SettingsButton => ("- settings -", "- parametres -"),
+            Label::Leave => ("- leave -", "- quitter -"),
+            Label::LevelList => ("---level-----\n------list---", "----liste----\n-des-niveaux-"),
+            Label::LevelEditor => ("---level-----\n----editor---", "----editeur--\n---de-niveaux"),
+            Label::LoadLevel => ("---load------\n-----level---", "---charger---\n---un-niveau-"),
+            Label::LoadFromClipboard => ("--load-from--\n--clipboard--", "--charger-le-\n--code-copie-"),
+        };
+        match self {
+            Language::English => english,
+            Language::French => french,
+        }
+    }
+}
+
+/// Texts of the menus, translated by key so that their wording and padding can change
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Label {
+    Settings,
+    Music,
+    Sounds,
+    Mute,
+    Scale,
+    Fullscreen,
+    Palette,
+    Language,
+    On,
+    Off,
+    Whole,
+    Fit,
+    Pico8,
+    Contrast,
+    Colorblind,
+    English,
+    French,
+    Back,
+    Paused,
+    Resume,
+    SettingsButton,
+    Leave,
+    LevelList,
+    LevelEditor,
+    LoadLevel,
+    LoadFromClipboard,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, EnumIter)]
+pub enum Setting {
+    Music,
+    Sounds,
+    Mute,
+    Scale,
+    Fullscreen,
+    Palette,
+    Language,
+}
+
+impl Setting {
+    fn label(&self) -> Label {
+        match self {
+            Setting::Music => Label::Music,
+            Setting::Sounds => Label::Sounds,
+            Setting::Mute => Label::Mute,
+            Setting::Scale => Label::Scale,
+            Setting::Fullscreen => Label::Fullscreen,
+            Setting::Palette => Label::Palette,
+            Setting::Language => Label::Language,
+        }
+    }
+
+    /// Sliders have a button on each side, the other settings are clicked on their value
+    fn slider(&self) -> bool {
+        matches!(self, Setting::Music | Setting::Sounds)
+    }
+}
+
+const KEY: &'static str = "settings";
+
+pub fn get_settings(
+    pkv: &PkvStore
+) -> Settings {
+    pkv.get::<Settings>(KEY).unwrap_or_default()
+}
+
+pub fn set_settings(
+    pkv: &mut PkvStore,
+    settings: &Settings,
+) {
+    pkv.set::<Settings>(KEY, settings).unwrap_or_default();
+}
+
+fn load_settings(
+    pkv: Res<PkvStore>,
+    mut settings: ResMut<Settings>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    *settings = get_settings(pkv.as_ref());
+    util::set_palette(settings.palette);
+    clear_color.0 = Colors::DarkRed.get();
+}
+
+/// Everything on screen takes the colors of the new palette
+fn apply_palette(
+    settings: Res<Settings>,
+    mut previous: Local<Option<Palette>>,
+    mut clear_color: ResMut<ClearColor>,
+    mut chars: Query<&mut TextModeTextureAtlasSprite>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    let palette = settings.palette;
+    let from = previous.replace(palette).unwrap_or(palette);
+    if from == palette { return; }
+
+    util::set_palette(palette);
+    let recolor = |color: &mut Color| {
+        let [r, g, b, a] = color.as_rgba_f32();
+        let same = |c: Color| {
+            let [r2, g2, b2, _] = c.as_rgba_f32();
+            (r - r2).abs() + (g - g2).abs() + (b - b2).abs() < 0.01
+        };
+        if let Some(c) = Colors::iter().find(|c| same(c.get_in(from))) {
+            *color = *c.get_in(palette).set_a(a);
+        }
+    };
+
+    recolor(&mut clear_color.0);
+    for mut sprite in chars.iter_mut() {
+        recolor(&mut sprite.bg);
+        recolor(&mut sprite.fg);
+    }
+    for mut sprite in sprites.iter_mut() {
+        recolor(&mut sprite.color);
+    }
+}
+
+#[derive(Component)]
+struct SettingsUI;
+
+/// Texts of the screen, written again when the settings change
+#[derive(Component, Copy, Clone)]
+enum SettingsText {
+    Title,
+    Label(Setting),
+    Value(Setting),
+    Back,
+}
+
+impl SettingsText {
+    /// Padded so that they keep their length in every language
+    fn text(&self, settings: &Settings) -> String {
+        let language = settings.language;
+        match self {
+            SettingsText::Title => format!("{:^10}", language.translate(Label::Settings)),
+            SettingsText::Label(setting) => format!("{:<12}", language.translate(setting.label())),
+            SettingsText::Value(setting) => format!("{:<10}", settings.value(*setting)),
+            SettingsText::Back => format!("{:^10}", language.translate(Label::Back)),
+        }
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    textures: Res<Textures>,
+    settings: Res<Settings>,
+) {
+    // Hides the screen the settings were opened from
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: Colors::DarkRed.get(),
+                custom_size: Some(Vec2::new(WIDTH, HEIGHT)),
+                anchor: Anchor::BottomLeft,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(0., 0., util::z::MENU_BG),
+            ..Default::default()
+        })
+        .insert(SettingsUI);
+
+    let (w, h) = (34, 21);
+    let x = ((WIDTH - (w + 2) as f32 * 8.) / 2.).round();
+    let y = ((HEIGHT - (h + 2) as f32 * 8.) / 2.).round();
+    let id = util::frame(
+        &mut commands, &textures,
+        x, y, util::z::MENU_FRAME,
+        w, h,
+        Colors::DarkRed, Colors::Beige
+    );
+    commands.entity(id).insert(SettingsUI);
+
+    let mut texts = vec![
+        (x + 104., y + 160., SettingsText::Title, None),
+        (x + 104., y + 16., SettingsText::Back, Some(TextButtonId::LeaveSettings)),
+    ];
+    for (i, setting) in Setting::iter().enumerate() {
+        let row = y + 136. - 16. * i as f32;
+        texts.push((x + 16., row, SettingsText::Label(setting), None));
+        if setting.slider() {
+            texts.push((x + 144., row, SettingsText::Value(setting), None));
+        } else {
+            texts.push((x + 144., row, SettingsText::Value(setting), Some(TextButtonId::Setting(setting, 1))));
+        }
+    }
+
+    for (text_x, text_y, text, button) in texts {
+        let id = text::spawn_text(
+            &mut commands, &textures,
+            Vec3::new(text_x, text_y, util::z::MENU),
+            &text.text(&settings),
+            Colors::Beige, Colors::DarkRed,
+        );
+        commands.entity(id)
+            .insert(SettingsUI)
+            .insert(text);
+        if let Some(button) = button { commands.entity(id).insert(button); }
+    }
+
+    // Both sides of the sliders
+    for (i, setting) in Setting::iter().enumerate().filter(|(_, s)| s.slider()) {
+        let row = y + 136. - 16. * i as f32;
+        for (text_x, text, step) in [(x + 128., "<", -1), (x + 232., ">", 1)] {
+            let id = text::spawn_text(
+                &mut commands, &textures,
+                Vec3::new(text_x, row, util::z::MENU),
+                text,
+                Colors::Beige, Colors::DarkRed,
+            );
+            commands.entity(id)
+                .insert(SettingsUI)
+                .insert(TextButtonId::Setting(setting, step));
+        }
+    }
+}
+
+fn click_on_button(
+    mut clicks: EventReader<ButtonClick>,
+    mut settings: ResMut<Settings>,
+    mut pkv: ResMut<PkvStore>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for click in clicks.iter() {
+        match click.0 {
+            TextButtonId::Setting(setting, step) => {
+                settings.change(setting, step);
+                set_settings(pkv.as_mut(), &settings);
+            }
+
+            TextButtonId::LeaveSettings => {
+                state.pop().unwrap_or_default();
+            }
+
+            _ => {}
+        }
+    }
+}
+
+/// Values and languages are applied live, F11 can also change the fullscreen setting
+fn update_texts(
+    settings: Res<Settings>,
+    texts: Query<(Entity, &SettingsText)>,
+    mut change_text: EventWriter<ChangeText>,
+) {
+    if !settings.is_changed() { return; }
+    for (e, text) in texts.iter() {
+        change_text.send(ChangeText(e, text.text(&settings)));
+    }
+}
+
+fn cleanup(
+    mut commands: Commands,
+    query: Query<Entity, With<SettingsUI>>,
+) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+#[test]
+fn test_change() {
+    let mut settings = Settings::default();
+    assert_eq!(settings.bgm_volume(), 0.4);
+    assert_eq!(settings.sfx_volume(), 0.3);
+
+    settings.change(Setting::Music, 1);
+    settings.change(Setting::Music, 1);
+    settings.change(Setting::Music, 1);
+    assert_eq!(settings.music, MAX_VOLUME);
+    settings.change(Setting::Mute, 1);
+    assert_eq!(settings.bgm_volume(), 0.);
+
+    settings.change(Setting::Palette, -1);
+    assert_eq!(settings.palette, Palette::Colorblind);
+    settings.change(Setting::Palette, 1);
+    assert_eq!(settings.palette, Palette::Pico8);
+}
+
+#[test]
+fn test_translate() {
+    assert_eq!(Language::French.translate(Label::Settings), "parametres");
+
+    // Title buttons are two lines of the same width in every language
+    for language in Language::iter() {
+        for label in [Label::LevelList, Label::LevelEditor, Label::LoadLevel, Label::LoadFromClipboard] {
+            assert!(language.translate(label).split('\n').all(|line| line.len() == 13));
+        }
+    }
+}
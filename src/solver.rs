@@ -0,0 +1,376 @@
+use bevy::utils::HashMap;
+use strum::IntoEnumIterator;
+
+use crate::puzzle;
+use crate::puzzle::Puzzle;
+use crate::veggie::Veggie;
+
+/// Plant the veggies left in the inventory on the free tiles so that every veggie is happy.
+/// None if there is no solution or the search gave up.
+pub fn solve(puzzle: &Puzzle) -> Option<HashMap<(i8, i8), Veggie>> {
+    let mut solutions = vec![];
+    Search::new(puzzle).run(0, &mut solutions, 1);
+    solutions.pop()
+}
+
+/// Number of solutions of the puzzle, counting at most up to `limit`,
+/// and whether the count is complete: `false` if the search gave up before.
+pub fn count_solutions(puzzle: &Puzzle, limit: usize) -> (usize, bool) {
+    let mut solutions = vec![];
+    let mut search = Search::new(puzzle);
+    search.run(0, &mut solutions, limit);
+    (solutions.len(), solutions.len() >= limit || search.nodes <= MAX_SOLVE_NODES)
+}
+
+/// Nodes visited before a search gives up, so that large gardens don't freeze the game
+const MAX_SOLVE_NODES: usize = 100_000;
+/// Nodes of the best score search cost more, every one of them bounds the score
+const MAX_SCORE_NODES: usize = 20_000;
+
+/// Best score of a scored garden once all the veggies left in the inventory are planted,
+/// or the best one found before the search gives up.
+pub fn best_score(puzzle: &Puzzle) -> Option<usize> {
+    let mut search = Best::new(puzzle);
+    search.run(0);
+    search.best
+}
+
+/// Level as players get it from its solution: the veggies placed there that aren't givens are the inventory.
+pub fn level(solution: &Puzzle) -> Puzzle {
+    let mut puzzle = solution.clone();
+    puzzle.veggies.clear();
+    for (_, v) in solution.placed.iter().filter(|(p, _)| !solution.givens.contains(*p)) {
+        *puzzle.veggies.entry(*v).or_insert(0) += 1;
+    }
+    puzzle.prepare();
+    puzzle
+}
+
+/// Par of a scored garden, from its solution.
+pub fn par(solution: &Puzzle) -> usize {
+    best_score(&level(solution)).unwrap_or(0).max(solution.score())
+}
+
+/// Fewest moves to solve a garden, from its solution: every veggie of the inventory
+/// is planted once, straight where the solution has it.
+pub fn par_moves(solution: &Puzzle) -> usize {
+    solution.placed.keys().filter(|p| !solution.givens.contains(*p)).count()
+}
+
+/// Branch and bound over the free tiles for the best score
+struct Best {
+    puzzle: Puzzle,
+    cells: Vec<(i8, i8)>,
+    remaining: Vec<(Veggie, usize)>,
+    /// Index of the last free tile around each tile, once it is decided the crop there can only drop
+    last: HashMap<(i8, i8), usize>,
+    /// Most a veggie can yield: happy on both sides, next to every other kind
+    max_crop: usize,
+    /// Tiles left to decide that are already covered by a big veggie
+    covered: usize,
+    best: Option<usize>,
+    nodes: usize,
+}
+
+impl Best {
+    fn new(puzzle: &Puzzle) -> Self {
+        let cells = puzzle.free_tiles();
+        let remaining = Veggie::iter()
+            .map(|v| (v, puzzle.remaining_veggie(&v, false)))
+            .filter(|(_, n)| *n > 0)
+            .collect::<Vec<_>>();
+
+        let mut last = HashMap::default();
+        for y in 0..puzzle.size.1 {
+            for x in 0..puzzle.size.0 {
+                if let Some(i) = puzzle.neighbours((x, y))
+                    .iter()
+                    .chain([(x, y)].iter())
+                    .filter_map(|p| cells.iter().position(|c| c == p))
+                    .max() {
+                    last.insert((x, y), i);
+                }
+            }
+        }
+
+        let mut kinds = remaining.iter().map(|(v, _)| *v).collect::<Vec<_>>();
+        kinds.extend(puzzle.placed.values());
+        kinds.sort_by_key(|v| v.name());
+        kinds.dedup();
+
+        Best { puzzle: puzzle.clone(), cells, remaining, last, max_crop: 2 + kinds.len(), covered: 0, best: None, nodes: 0 }
+    }
+
+    /// Highest score the garden can still reach once `cells[..i]` are decided
+    fn bound(&self, i: usize) -> usize {
+        let planted = self.puzzle.placed.iter().map(|(p, v)| {
+            // Veggies only get sadder as far away tiles fill up, big ones see further
+            let settled = v.span() == 1 && self.last.get(p).map_or(true, |l| *l < i);
+            if settled { puzzle::crop(v, *p, &self.puzzle) } else { self.max_crop }
+        }).sum::<usize>();
+        planted + self.remaining.iter().map(|(_, n)| n * self.max_crop).sum::<usize>()
+    }
+
+    fn run(&mut self, i: usize) {
+        self.nodes += 1;
+        if self.nodes > MAX_SCORE_NODES { return; }
+
+        let needed = self.remaining.iter().map(|(v, n)| *n * v.footprint().len()).sum::<usize>();
+        if needed == 0 {
+            let score = self.puzzle.score();
+            if self.best.map_or(true, |b| score > b) { self.best = Some(score); }
+            return;
+        }
+        let left = self.cells.len() - i - self.covered;
+        if needed > left { return; }
+        if self.best.map_or(false, |b| self.bound(i) <= b) { return; }
+
+        // Already taken by a big veggie planted earlier
+        let cell = self.cells[i];
+        if self.puzzle.occupant(cell).is_some() {
+            self.covered -= 1;
+            self.run(i + 1);
+            self.covered += 1;
+            return;
+        }
+
+        for r in 0..self.remaining.len() {
+            let (veggie, count) = self.remaining[r];
+            if count == 0 || !self.puzzle.fits(&veggie, cell) { continue; }
+
+            let covers = veggie.footprint().len() - 1;
+            self.puzzle.placed.insert(cell, veggie);
+            self.remaining[r].1 -= 1;
+            self.covered += covers;
+            self.run(i + 1);
+            self.covered -= covers;
+            self.remaining[r].1 += 1;
+            self.puzzle.placed.remove(&cell);
+        }
+
+        // Or leave the tile empty
+        if needed < left { self.run(i + 1); }
+    }
+}
+
+struct Search {
+    puzzle: Puzzle,
+    /// Free tiles, in the order they are decided
+    cells: Vec<(i8, i8)>,
+    remaining: Vec<(Veggie, usize)>,
+    /// Tiles whose whole neighbourhood is decided once `cells[i]` is
+    settled_after: Vec<Vec<(i8, i8)>>,
+    /// Tiles whose neighbourhood is decided: a veggie there must already be happy
+    settled: Vec<(i8, i8)>,
+    /// Tiles left to decide that are already covered by a big veggie
+    covered: usize,
+    nodes: usize,
+}
+
+impl Search {
+    fn new(puzzle: &Puzzle) -> Self {
+        let cells = puzzle.free_tiles();
+        let remaining = Veggie::iter()
+            .map(|v| (v, puzzle.remaining_veggie(&v, false)))
+            .filter(|(_, n)| *n > 0)
+            .collect();
+
+        let mut settled_after = vec![vec![]; cells.len()];
+        let mut settled = vec![];
+        for y in 0..puzzle.size.1 {
+            for x in 0..puzzle.size.0 {
+                let last = puzzle.neighbours((x, y))
+                    .iter()
+                    .chain([(x, y)].iter())
+                    .filter_map(|p| cells.iter().position(|c| c == p))
+                    .max();
+                match last {
+                    Some(i) => settled_after[i].push((x, y)),
+                    None => settled.push((x, y)),
+                }
+            }
+        }
+
+        Search { puzzle: puzzle.clone(), cells, remaining, settled_after, settled, covered: 0, nodes: 0 }
+    }
+
+    fn run(&mut self, i: usize, solutions: &mut Vec<HashMap<(i8, i8), Veggie>>, limit: usize) {
+        if solutions.len() >= limit { return; }
+        self.nodes += 1;
+        if self.nodes > MAX_SOLVE_NODES { return; }
+
+        // Tiles still needed, big veggies taking several
+        let needed = self.remaining.iter().map(|(v, n)| *n * v.footprint().len()).sum::<usize>();
+        if i == self.cells.len() {
+            if needed == 0 && self.puzzle.is_valid().is_ok() {
+                solutions.push(self.puzzle.placed.clone());
+            }
+            return;
+        }
+        let left = self.cells.len() - i - self.covered;
+        if needed > left { return; }
+
+        let cell = self.cells[i];
+        let settled = self.settled.len();
+
+        // Already taken by a big veggie planted earlier
+        if self.puzzle.occupant(cell).is_some() {
+            self.covered -= 1;
+            self.settled.extend(self.settled_after[i].iter());
+            if self.settled_happy() { self.run(i + 1, solutions, limit); }
+            self.settled.truncate(settled);
+            self.covered += 1;
+            return;
+        }
+
+        for r in 0..=self.remaining.len() {
+            // Last option: leave the tile empty
+            if r == self.remaining.len() {
+                if needed == left { continue; }
+                self.settled.extend(self.settled_after[i].iter());
+                if self.settled_happy() { self.run(i + 1, solutions, limit); }
+                self.settled.truncate(settled);
+                continue;
+            }
+
+            let (veggie, count) = self.remaining[r];
+            if count == 0 || !self.fits(&veggie, cell) { continue; }
+
+            let covers = veggie.footprint().len() - 1;
+            self.puzzle.placed.insert(cell, veggie);
+            self.remaining[r].1 -= 1;
+            self.covered += covers;
+            self.settled.extend(self.settled_after[i].iter());
+            if self.settled_happy() { self.run(i + 1, solutions, limit); }
+            self.settled.truncate(settled);
+            self.covered -= covers;
+            self.remaining[r].1 += 1;
+            self.puzzle.placed.remove(&cell);
+
+            if solutions.len() >= limit { return; }
+        }
+    }
+
+    /// Can't be planted next to a veggie it bothers or that bothers it
+    fn fits(&self, veggie: &Veggie, cell: (i8, i8)) -> bool {
+        // Sad veggies may be tolerated, it is up to the settled tiles to count them
+        if self.puzzle.sad > 0 { return self.puzzle.fits(veggie, cell); }
+        if !self.puzzle.fits(veggie, cell) || self.puzzle.crowding(veggie, cell).is_some() { return false; }
+        if puzzle::needs_sun(veggie) && self.puzzle.shaded_by(veggie, cell).is_some() { return false; }
+        if self.puzzle.shades(veggie, cell).is_some() { return false; }
+
+        let unhappy = puzzle::unhappy_adjacent(veggie);
+        self.puzzle.adjacent_veggies(veggie, cell)
+            .iter()
+            .all(|n| !unhappy.contains(n) && !puzzle::unhappy_adjacent(n).contains(veggie))
+    }
+
+    /// No more veggies are sad on the settled tiles than the puzzle tolerates
+    fn settled_happy(&self) -> bool {
+        self.settled.iter().filter(|pos| match self.puzzle.placed.get(pos) {
+            Some(v) => puzzle::is_happy(v, **pos, &self.puzzle) != (true, true),
+            None => false,
+        }).count() <= self.puzzle.sad as usize
+    }
+}
+
+#[test]
+fn test_solve_levels() {
+    use crate::data::Decoder;
+    use crate::levels::LEVELS;
+
+    for level in LEVELS.iter().filter(|l| !l.is_empty()) {
+        let mut puzzle = Decoder::decode_puzzle(level.to_string()).unwrap();
+        puzzle.prepare();
+        let solution = solve(&puzzle).expect(level);
+        puzzle.placed = solution;
+        assert!(puzzle.is_valid().is_ok());
+    }
+}
+
+#[test]
+fn test_count_solutions() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.veggies.insert(Veggie::Strawberry, 2);
+
+    // Both strawberries on the left or both on the right
+    assert_eq!(count_solutions(&puzzle, 10), (2, true));
+
+    puzzle.veggies.insert(Veggie::Apple, 1);
+    assert_eq!(count_solutions(&puzzle, 10), (0, true));
+
+    // An odd number of cherries can't all be paired, large gardens give up before finding out
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (10, 10);
+    puzzle.veggies.insert(Veggie::Cherry, 3);
+    assert_eq!(count_solutions(&puzzle, 10), (0, false));
+}
+
+#[test]
+fn test_solve_pumpkin() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (4, 2);
+    puzzle.veggies.insert(Veggie::Pumpkin, 1);
+    puzzle.veggies.insert(Veggie::Strawberry, 2);
+
+    // Pumpkin on one side, strawberries on the other, an empty column between them
+    assert_eq!(count_solutions(&puzzle, 10), (2, true));
+    assert_eq!(solve(&puzzle).unwrap().len(), 3);
+}
+
+#[test]
+fn test_best_score() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.veggies.insert(Veggie::Tomato, 1);
+    puzzle.veggies.insert(Veggie::Garlic, 1);
+
+    // Side by side, both happy with a companion
+    assert_eq!(best_score(&puzzle), Some(8));
+
+    puzzle.veggies.insert(Veggie::Apple, 2);
+    assert_eq!(best_score(&puzzle), None);
+
+    // Large gardens give up with the best score found so far
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (20, 20);
+    for v in [Veggie::Tomato, Veggie::Garlic, Veggie::Carrot, Veggie::Strawberry, Veggie::Mint] {
+        puzzle.veggies.insert(v, 2);
+    }
+    assert!(best_score(&puzzle).is_some());
+}
+
+#[test]
+fn test_solve_tolerance() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.veggies.insert(Veggie::Strawberry, 2);
+    puzzle.veggies.insert(Veggie::Apple, 1);
+    assert_eq!(count_solutions(&puzzle, 10), (0, true));
+
+    // The apple tree on a side bothers one strawberry
+    puzzle.sad = 1;
+    puzzle.exact_sad = true;
+    assert_eq!(count_solutions(&puzzle, 10), (2, true));
+
+    // In the middle, it bothers both
+    puzzle.sad = 2;
+    assert_eq!(count_solutions(&puzzle, 10), (1, true));
+    puzzle.exact_sad = false;
+    assert_eq!(count_solutions(&puzzle, 10), (3, true));
+}
+
+#[test]
+fn test_par_moves() {
+    let mut puzzle = Puzzle::default();
+    puzzle.size = (3, 1);
+    puzzle.placed.insert((0, 0), Veggie::Strawberry);
+    puzzle.placed.insert((1, 0), Veggie::Strawberry);
+    assert_eq!(par_moves(&puzzle), 2);
+
+    // Givens are already planted
+    puzzle.placed.insert((2, 0), Veggie::Strawberry);
+    puzzle.givens.insert((2, 0));
+    assert_eq!(par_moves(&puzzle), 2);
+}
@@ -1,5 +1,3 @@
-use std::ops::Add;
-
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 use bevy_text_mode::{TextModeSpriteSheetBundle, TextModeTextureAtlasSprite};
@@ -7,7 +5,11 @@ use bevy_text_mode::{TextModeSpriteSheetBundle, TextModeTextureAtlasSprite};
 use crate::audio::{PlaySfxEvent, SFX};
 use crate::loading::Textures;
 use crate::overworld::Slot;
-use crate::util::{collides, Colors};
+use crate::{BlockInput, GameState, util};
+use crate::input::{Action, Actions, Clickable};
+use crate::inventory::Held;
+use crate::settings::Setting;
+use crate::util::Colors;
 
 pub struct TextPlugin;
 
@@ -16,8 +18,11 @@ impl Plugin for TextPlugin {
         app
             .add_event::<ChangeText>()
             .add_event::<ButtonClick>()
+            .init_resource::<ButtonFocus>()
             .add_system(update_text)
+            .add_system(update_clickables)
             .add_system(handle_click)
+            .add_system(handle_actions)
         ;
     }
 }
@@ -123,10 +128,24 @@ pub enum TextButtonId {
     Export,
     Import,
     Clear,
+    Adjacency,
+    Wrap,
+    FenceTool,
     LeaveEditor,
     LeaveLevel,
+    Retry,
+    Grow,
+    Seasons,
+    Scored,
+    Tolerance,
+    Moves,
     LeaveOverworld,
     LeaveTutorial,
+    Resume,
+    Quit,
+    Settings,
+    Setting(Setting, i8),
+    LeaveSettings,
     Title(u8),
     Overworld(Slot),
     Tutorial(bool),
@@ -134,22 +153,114 @@ pub enum TextButtonId {
 
 pub struct ButtonClick(pub TextButtonId);
 
+/// Button selected with the keyboard, drawn with its colors swapped
+#[derive(Resource, Default)]
+pub struct ButtonFocus(pub Option<Entity>);
+
+/// Buttons are clicked on their whole text
+fn update_clickables(
+    mut commands: Commands,
+    buttons: Query<(Entity, &Text), (With<TextButtonId>, Or<(Changed<Text>, Added<TextButtonId>)>)>,
+) {
+    for (e, text) in buttons.iter() {
+        let (width, height) = (text.0.get(0).unwrap().len() as f32 * 8., text.0.len() as f32 * 8.);
+        commands.entity(e).insert(Clickable::new(Vec2::new(0., 8. - height), Vec2::new(width, height)));
+    }
+}
+
+/// Popups and menus take the input from the buttons under them
+fn lowest_reachable(mut depths: impl Iterator<Item=f32>) -> f32 {
+    if depths.any(|z| z >= util::z::POPUP) { util::z::POPUP } else { f32::MIN }
+}
+
 fn handle_click(
-    buttons: Query<(&Text, &Transform, &TextButtonId)>,
-    mouse: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+    buttons: Query<(&Clickable, &Transform, &TextButtonId)>,
+    actions: Res<Actions>,
+    mut ev: EventWriter<ButtonClick>,
+    mut sfx: EventWriter<PlaySfxEvent>,
+) {
+    if actions.just_pressed(Action::Press) {
+        let lowest = lowest_reachable(buttons.iter().map(|(_, t, _)| t.translation.z));
+        if let Some((_, _, id)) = buttons.iter().find(|(c, t, _)| c.hovered && t.translation.z >= lowest) {
+            sfx.send(PlaySfxEvent(SFX::Clic));
+            ev.send(ButtonClick(id.clone()));
+        }
+    }
+}
+
+/// Tab, Shift+Tab and the gamepad triggers cycle through the buttons on screen,
+/// Confirm clicks the selected one and Leave clicks the leave button of the screen.
+/// Screens without a garden can also be browsed with the arrows.
+fn handle_actions(
+    actions: Res<Actions>,
+    buttons: Query<(Entity, &Transform, &TextButtonId)>,
+    children: Query<&Children>,
+    mut chars: Query<&mut TextModeTextureAtlasSprite>,
+    mut focus: ResMut<ButtonFocus>,
+    state: Res<State<GameState>>,
+    block_input: Res<BlockInput>,
+    held: Query<(), With<Held>>,
     mut ev: EventWriter<ButtonClick>,
     mut sfx: EventWriter<PlaySfxEvent>,
 ) {
-    if mouse.just_pressed(MouseButton::Left) {
-        let window = windows.get_primary().unwrap();
-        if let Some(pos) = window.cursor_position() {
-            if let Some((_, _, id)) = buttons.iter().filter(|(text, t, _)|
-                collides(t.translation.add(Vec3::new(0., (text.0.len() - 1) as f32 * 8. * -1., 0.)), text.0.get(0).unwrap().len() as f32 * 8., text.0.len() as f32 * 8., pos)
-            ).nth(0) {
-                sfx.send(PlaySfxEvent(SFX::Clic));
-                ev.send(ButtonClick(id.clone()));
+    // The button went away with its screen
+    if focus.0.map_or(false, |e| buttons.get(e).is_err()) { focus.0 = None; }
+
+    let lowest = lowest_reachable(buttons.iter().map(|(_, t, _)| t.translation.z));
+    let reachable = buttons.iter().filter(|(_, t, _)| t.translation.z >= lowest).collect::<Vec<_>>();
+
+    let clicked = if actions.just_pressed(Action::Confirm) {
+        focus.0.and_then(|e| buttons.get(e).ok()).map(|(_, _, id)| id)
+    } else if actions.just_pressed(Action::Leave) && !block_input.0 && held.is_empty() {
+        reachable.iter().map(|(_, _, id)| *id).find(|id| matches!(id,
+            TextButtonId::LeaveLevel | TextButtonId::LeaveEditor | TextButtonId::LeaveOverworld | TextButtonId::LeaveTutorial
+                | TextButtonId::LeaveSettings
+        ))
+    } else {
+        None
+    };
+    if let Some(id) = clicked {
+        sfx.send(PlaySfxEvent(SFX::Clic));
+        ev.send(ButtonClick(id.clone()));
+    }
+
+    // Arrows move the grid cursor in gardens
+    let garden = [GameState::Play, GameState::Editor].contains(state.current());
+    let step = if actions.just_pressed(Action::NextButton) {
+        1
+    } else if actions.just_pressed(Action::PreviousButton) {
+        -1
+    } else if !garden && actions.any_just_pressed([Action::Right, Action::Down]) {
+        1
+    } else if !garden && actions.any_just_pressed([Action::Left, Action::Up]) {
+        -1
+    } else {
+        0
+    };
+
+    let next = if step != 0 {
+        // Reading order: top to bottom, then left to right
+        let mut order = reachable.iter().map(|(e, t, _)| (*e, t.translation)).collect::<Vec<_>>();
+        order.sort_by(|(_, a), (_, b)| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+        if order.is_empty() { return; }
+        let i = focus.0
+            .and_then(|f| order.iter().position(|(e, _)| *e == f))
+            .map_or(if step > 0 { 0 } else { order.len() - 1 }, |i| (i as isize + step).rem_euclid(order.len() as isize) as usize);
+        Some(order[i].0)
+    } else if !garden && actions.just_pressed(Action::Menu)
+        || garden && actions.any_just_pressed([Action::Left, Action::Right, Action::Up, Action::Down]) {
+        None
+    } else {
+        return;
+    };
+    if next == focus.0 { return; }
+
+    for e in [focus.0, next].iter().flatten() {
+        for child in children.get(*e).iter().flat_map(|c| c.iter()) {
+            if let Ok(mut sprite) = chars.get_mut(*child) {
+                (sprite.bg, sprite.fg) = (sprite.fg, sprite.bg);
             }
         }
     }
-}
\ No newline at end of file
+    focus.0 = next;
+}
@@ -11,6 +11,7 @@ use crate::data::Decoder;
 use crate::grid::CurrentPuzzle;
 use crate::loading::Textures;
 use crate::puzzle::Puzzle;
+use crate::settings::{Label, Settings};
 use crate::text::{ButtonClick, spawn_text, TextButtonId};
 use crate::util::Colors;
 use crate::veggie::{Expression, spawn_veggie, Veggie};
@@ -70,6 +71,7 @@ fn get_combination(n: u8) -> Vec<(Veggie, f32, f32, Expression)> {
 fn setup(
     mut commands: Commands,
     textures: Res<Textures>,
+    settings: Res<Settings>,
     mut bgm: EventWriter<PlayBgmEvent>,
 ) {
     bgm.send(PlayBgmEvent(BGM::Title));
@@ -116,20 +118,20 @@ fn setup(
 
     // Buttons
     #[cfg(target_arch = "wasm32")]
-        let load = "---load------\n-----level---";
+        let load = Label::LoadLevel;
     #[cfg(not(target_arch = "wasm32"))]
-        let load = "--load-from--\n--clipboard--";
+        let load = Label::LoadFromClipboard;
 
     for (text, x, y, button) in [
-        ("---level-----\n------list---".to_string(), WIDTH / 2. - 8. * 5.5, 184. + 16., TextButtonId::Title(0)),
-        (load.to_string(), WIDTH / 2. - 8. * 5.5, 184. - 16., TextButtonId::Title(1)),
-        ("---level-----\n----editor---".to_string(), WIDTH / 2. - 8. * 5.5, 184. - 48., TextButtonId::Title(2)),
+        (Label::LevelList, WIDTH / 2. - 8. * 5.5, 184. + 16., TextButtonId::Title(0)),
+        (load, WIDTH / 2. - 8. * 5.5, 184. - 16., TextButtonId::Title(1)),
+        (Label::LevelEditor, WIDTH / 2. - 8. * 5.5, 184. - 48., TextButtonId::Title(2)),
     ] {
         let id = spawn_text(
             &mut commands,
             &textures,
             Vec3::new(x, y, util::z::TITLE_BUTTONS),
-            &text,
+            settings.language.translate(text),
             Colors::Beige,
             Colors::DarkRed,
         );
@@ -140,6 +142,20 @@ fn setup(
             .insert(TitleUI);
     }
 
+    let settings_text = settings.language.translate(Label::Settings);
+    let id = spawn_text(
+        &mut commands,
+        &textures,
+        Vec3::new(WIDTH - 8. - settings_text.len() as f32 * 8., HEIGHT - 16., util::z::TITLE_BUTTONS),
+        settings_text,
+        Colors::Beige,
+        Colors::DarkRed,
+    );
+    commands
+        .entity(id)
+        .insert(TextButtonId::Title(3))
+        .insert(TitleUI);
+
     // All veggies
     for (i, v) in Veggie::iter().enumerate() {
         let id = spawn_veggie(
@@ -255,10 +271,13 @@ fn update(
                         }
                     }
                 },
-                _ => {
+                2 => {
                     commands.insert_resource(CurrentPuzzle(Some(Puzzle::default())));
                     state.push(GameState::Editor).unwrap();
                 },
+                _ => {
+                    state.push(GameState::Settings).unwrap();
+                },
             },
             _ => {}
         }
@@ -4,6 +4,7 @@ use bevy_pkv::PkvStore;
 use crate::{GameState, progress, text, util, WIDTH};
 use crate::loading::Textures;
 use crate::overworld::{CurrentSlot, Slot};
+use crate::puzzle::Adjacency;
 use crate::text::{ButtonClick, TextButtonId};
 use crate::util::Colors;
 use crate::veggie::{Expression, spawn_veggie, Veggie};
@@ -41,7 +42,7 @@ fn setup(
          drag them on an empty tile of the grid.\n\n\
          the first veggie is the strawberry:\n\
          it likes being adjacent (diagonals count)\n\
-         to some other strawberries."),
+         to some other strawberries.", vec![]),
         (vec![
              (Veggie::Carrot, 2, 8, Expression::Happy),
              (Veggie::Garlic, 8, 8, Expression::Happy),
@@ -49,19 +50,19 @@ fn setup(
          ],
          "\n\n\n\n\n\
          tomatoes will be happy if they are adjacent\n\n\
-         to garlic or carrots!"),
+         to garlic or carrots!", vec![]),
         (vec![
              (Veggie::Garlic, 5, 12, Expression::Sad),
          ],
          "\n\n\n\n\n\
          the garlic likes dry spots.\n\n\
-         it will become sad when adjacent to water!"),
+         it will become sad when adjacent to water!", vec![]),
         (vec![
              (Veggie::Carrot, 5, 12, Expression::Sad),
          ],
          "\n\n\n\n\n\
          the carrot likes a clean soil.\n\n\
-         it will become sad when adjacent to a rock!"),
+         it will become sad when adjacent to a rock!", vec![]),
         (vec![
              (Veggie::Carrot, 2, 8, Expression::Sad),
              (Veggie::Garlic, 8, 8, Expression::Sad),
@@ -70,7 +71,7 @@ fn setup(
          "\n\n\n\n\n\
          the mint has thick tangled roots.\n\n\
          it will bother adjacent carrots and garlics\n\
-         and make them sad!"),
+         and make them sad!", vec![]),
         (vec![
              (Veggie::Carrot, 2, 6, Expression::Sad),
              (Veggie::Garlic, 8, 6, Expression::Sad),
@@ -81,7 +82,7 @@ fn setup(
          "\n\n\n\n\n\
          apple trees have a nice foliage.\n\n\
          they will cast shadow and bother\n\
-         any adjacent veggie!"),
+         any adjacent veggie!", vec![]),
         (vec![
              (Veggie::Cherry, 2, 8, Expression::Happy),
              (Veggie::Cherry, 8, 8, Expression::Sad),
@@ -93,9 +94,45 @@ fn setup(
          they are also very jealous and will become\n\
          sad if there is an apple tree in\n\
          their line or column!\n\n\
-         you know all the rules now, you can try the\n\
-         level editor and share your best levels in\n\
-         the comments :)"),
+         the next pages show gardens with their own\n\
+         rules. after them, try the level editor and\n\
+         share your best levels in the comments :)", vec![]),
+        (vec![
+             (Veggie::Strawberry, 5, 12, Expression::Happy),
+         ],
+         "\n\
+         by default, diagonals count: a veggie has\n\
+         8 neighbours.\n\n\
+         some gardens count neighbours differently.\n\
+         the level panel tells you which ones count:", vec![Adjacency::Eight, Adjacency::Four, Adjacency::Knight]),
+        (vec![
+             (Veggie::Corn, 2, 8, Expression::Happy),
+             (Veggie::Bean, 8, 8, Expression::Happy),
+             (Veggie::Squash, 5, 13, Expression::Happy),
+         ],
+         "\n\n\n\n\
+         corn, beans and squash: the three sisters.\n\n\
+         they only grow well together: each one\n\
+         needs the two others adjacent to it!", vec![]),
+        (vec![
+             (Veggie::Sunflower, 5, 4, Expression::Happy),
+             (Veggie::Tomato, 5, 12, Expression::Sad),
+             (Veggie::Garlic, 5, 20, Expression::Happy),
+         ],
+         "\n\n\n\n\
+         the sunflower needs full sun, and its shadow\n\
+         covers the two tiles above it.\n\n\
+         sunflowers, tomatoes, strawberries and corn\n\
+         are sad in the shadow!", vec![]),
+        (vec![
+             (Veggie::Celery, 5, 4, Expression::Happy),
+             (Veggie::Garlic, 5, 12, Expression::Sad),
+         ],
+         "\n\n\n\n\
+         water flows from the sources along the\n\
+         channels connected to them.\n\n\
+         celery is thirsty: it needs a source or a\n\
+         wet channel around, but garlic hates it!", vec![]),
     ];
 
     if let Some(s) = slot {
@@ -135,6 +172,26 @@ fn setup(
         );
         commands.entity(id).insert(TutorialUI);
 
+        // Neighbourhood diagrams
+        for (i, adjacency) in page.2.iter().enumerate() {
+            let x = 128. + 64. + i as f32 * 96.;
+            let id = text::spawn_text(
+                &mut commands, &textures,
+                Vec3::new(x + 8., 160., util::z::VEG_UI),
+                &neighbourhood(adjacency),
+                Colors::Beige, Colors::DarkRed
+            );
+            commands.entity(id).insert(TutorialUI);
+
+            let id = text::spawn_text(
+                &mut commands, &textures,
+                Vec3::new(x, 112., util::z::VEG_UI),
+                adjacency.label(),
+                Colors::Beige, Colors::DarkRed
+            );
+            commands.entity(id).insert(TutorialUI);
+        }
+
     }
 
     let id = util::frame(
@@ -156,6 +213,17 @@ fn setup(
         .insert(TutorialUI);
 }
 
+/// 5x5 drawing of the tiles adjacent to the center one
+fn neighbourhood(adjacency: &Adjacency) -> String {
+    let offsets = adjacency.offsets(0);
+    (-2..=2).rev()
+        .map(|dy| (-2..=2)
+            .map(|dx| if dx == 0 && dy == 0 { 'o' } else if offsets.contains(&(dx, dy)) { '#' } else { '.' })
+            .collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 fn click_on_button(
     mut clicked: EventReader<ButtonClick>,
     mut state: ResMut<State<GameState>>,
@@ -1,12 +1,18 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
 use bevy::asset::Handle;
 use bevy::hierarchy::BuildChildren;
 use bevy::math::{Vec2, Vec3};
 use bevy::prelude::{Color, Commands, ComputedVisibility, Entity, GlobalTransform, Res, TextureAtlas, Transform, Visibility};
+use bevy::window::Window;
 use bevy::sprite::Anchor;
 use bevy_text_mode::{TextModeSpriteSheetBundle, TextModeTextureAtlasSprite};
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
 
 use crate::loading::Textures;
 
+#[derive(Copy, Clone, Debug, EnumIter)]
 pub enum Colors {
     // PICO-8 palette
     Black,
@@ -16,14 +22,52 @@ pub enum Colors {
     Green,
     Orange,
     Grey,
+    Yellow,
     // Custom colors
     DarkBrown,
     Brown,
     DarkRed,
 }
 
+/// Set of colors the game is drawn with
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize, EnumIter)]
+pub enum Palette {
+    #[default]
+    Pico8,
+    /// Darker backgrounds and lighter texts
+    Contrast,
+    /// Green and red told apart by blue and orange
+    Colorblind,
+}
+
+/// Position of the current palette in `Palette::iter()`
+static PALETTE: AtomicU8 = AtomicU8::new(0);
+
+/// Colors spawned from now on use this palette
+pub fn set_palette(palette: Palette) {
+    let index = Palette::iter().position(|p| p == palette).unwrap_or(0);
+    PALETTE.store(index as u8, Ordering::Relaxed);
+}
+
 impl Colors {
     pub fn get(&self) -> Color {
+        let palette = Palette::iter().nth(PALETTE.load(Ordering::Relaxed) as usize).unwrap_or_default();
+        self.get_in(palette)
+    }
+
+    pub fn get_in(&self, palette: Palette) -> Color {
+        match (palette, self) {
+            (Palette::Contrast, Colors::Beige) => Color::hex("fdfdf8").unwrap(),
+            (Palette::Contrast, Colors::DarkRed) => Color::hex("1e1010").unwrap(),
+            (Palette::Contrast, Colors::Brown) => Color::hex("4a1a0c").unwrap(),
+            (Palette::Contrast, Colors::DarkBrown) => Color::hex("2a0c02").unwrap(),
+            (Palette::Colorblind, Colors::Red) => Color::hex("ff6c24").unwrap(),
+            (Palette::Colorblind, Colors::Green) => Color::hex("29adff").unwrap(),
+            _ => self.get_pico8(),
+        }
+    }
+
+    fn get_pico8(&self) -> Color {
         match self {
             Colors::Black => Color::BLACK,
             Colors::Navy => Color::hex("1d2b53").unwrap(),
@@ -32,6 +76,7 @@ impl Colors {
             Colors::Green => Color::hex("008751").unwrap(),
             Colors::Orange => Color::hex("ffa300").unwrap(),
             Colors::Grey => Color::hex("c2c3c7").unwrap(),
+            Colors::Yellow => Color::hex("ffec27").unwrap(),
             Colors::DarkBrown => Color::hex("441506").unwrap(),
             Colors::Brown => Color::hex("662916").unwrap(),
             Colors::DarkRed => Color::hex("553737").unwrap(),
@@ -40,14 +85,23 @@ impl Colors {
 }
 
 pub mod z {
+    pub const TILE_BELOW: f32 = 0.5;
     pub const TILE: f32 = 1.;
     pub const TILE_ABOVE: f32 = 1.5;
+    pub const SHADE: f32 = 1.8;
+    pub const CURSOR: f32 = 1.9;
     pub const VEGGIE: f32 = 2.;
+    pub const FENCE: f32 = 2.5;
     pub const VEG_UI_BG: f32 = 3.;
     pub const VEG_UI: f32 = 3.2;
     pub const COUNT_TEXT: f32 = 3.4;
     pub const VEG_DRAG: f32 = 4.;
     pub const WIN_HEART: f32 = 5.;
+    pub const POPUP_BG: f32 = 6.;
+    pub const POPUP: f32 = 6.2;
+    pub const MENU_BG: f32 = 7.;
+    pub const MENU_FRAME: f32 = 7.1;
+    pub const MENU: f32 = 7.2;
 
     pub const TITLE_BUTTONS_BG: f32 = 1.;
     pub const TITLE_BUTTONS: f32 = 2.;
@@ -57,10 +111,15 @@ pub fn collides(
     collider: Vec3,
     width: f32,
     height: f32,
-    cursor_pos: Vec2,
+    world_pos: Vec2,
 ) -> bool {
-    (collider.x + width / 2. - cursor_pos.x / 2.).abs() < width / 2.
-        && (collider.y + height / 2. - cursor_pos.y / 2.).abs() < height / 2.
+    (collider.x + width / 2. - world_pos.x).abs() < width / 2.
+        && (collider.y + height / 2. - world_pos.y).abs() < height / 2.
+}
+
+/// Position seen at the same place on screen through another camera
+pub fn world_to_world(pos: Vec2, from: &Transform, to: &Transform) -> Vec2 {
+    to.translation.truncate() + (pos - from.translation.truncate()) * to.scale.truncate() / from.scale.truncate()
 }
 
 pub fn text_mode_bundle(
@@ -131,3 +190,12 @@ pub fn frame<'w, 's, 'a>(
         })
         .id()
 }
+
+#[test]
+fn test_palette() {
+    for palette in Palette::iter() {
+        set_palette(palette);
+        assert_eq!(Colors::Red.get(), Colors::Red.get_in(palette));
+    }
+    set_palette(Palette::default());
+}
@@ -1,12 +1,14 @@
 use bevy::prelude::*;
 use bevy::sprite::Anchor;
 use bevy_text_mode::TextModeTextureAtlasSprite;
+use serde::Deserialize;
 use strum::EnumIter;
 
+use crate::input::Clickable;
 use crate::loading::Textures;
 use crate::util::{Colors, text_mode_bundle};
 
-#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, Hash, Component)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug, EnumIter, Hash, Deserialize, Component)]
 pub enum Veggie {
     Strawberry,
     Tomato,
@@ -15,9 +17,34 @@ pub enum Veggie {
     Cherry,
     Garlic,
     Mint,
+    Pumpkin,
+    // The three sisters
+    Corn,
+    Bean,
+    Squash,
+    Sunflower,
+    Celery,
 }
 
 impl Veggie {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Veggie::Strawberry => "strawberry",
+            Veggie::Tomato => "tomato",
+            Veggie::Apple => "apple",
+            Veggie::Carrot => "carrot",
+            Veggie::Cherry => "cherry",
+            Veggie::Garlic => "garlic",
+            Veggie::Mint => "mint",
+            Veggie::Pumpkin => "pumpkin",
+            Veggie::Corn => "corn",
+            Veggie::Bean => "bean",
+            Veggie::Squash => "squash",
+            Veggie::Sunflower => "sunflower",
+            Veggie::Celery => "celery",
+        }
+    }
+
     pub fn sprite(&self) -> usize {
         match self {
             Veggie::Strawberry => 0,
@@ -27,9 +54,30 @@ impl Veggie {
             Veggie::Cherry => 4,
             Veggie::Garlic => 5,
             Veggie::Mint => 6,
+            Veggie::Corn => 7,
+            Veggie::Bean => 8,
+            Veggie::Squash => 9,
+            Veggie::Sunflower => 10,
+            Veggie::Celery => 11,
+            // In the big veggies atlas
+            Veggie::Pumpkin => 0,
+        }
+    }
+
+    /// Width and height in tiles
+    pub fn span(&self) -> i8 {
+        match self {
+            Veggie::Pumpkin => 2,
+            _ => 1,
         }
     }
 
+    /// Tiles covered, relative to the bottom left one
+    pub fn footprint(&self) -> Vec<(i8, i8)> {
+        let span = self.span();
+        (0..span).flat_map(|y| (0..span).map(move |x| (x, y))).collect()
+    }
+
     pub fn faces(&self) -> Vec<(f32, f32)> {
         match self {
             Veggie::Strawberry | Veggie::Apple => vec![(16., 16.)],
@@ -38,21 +86,31 @@ impl Veggie {
             Veggie::Cherry => vec![(8., 7.), (24., 7.)],
             Veggie::Garlic => vec![(16., 14.)],
             Veggie::Mint => vec![(8., 25.)],
+            Veggie::Pumpkin => vec![(36., 24.)],
+            Veggie::Corn => vec![(16., 16.)],
+            Veggie::Bean => vec![(16., 14.)],
+            Veggie::Squash => vec![(16., 12.)],
+            Veggie::Sunflower => vec![(16., 23.)],
+            Veggie::Celery => vec![(16., 14.)],
         }
     }
 
     pub fn face_bg(&self) -> Colors {
         match self {
             Veggie::Strawberry | Veggie::Tomato | Veggie::Cherry => Colors::Red,
-            Veggie::Apple | Veggie::Mint => Colors::Green,
-            Veggie::Carrot => Colors::Orange,
+            Veggie::Apple | Veggie::Mint | Veggie::Bean | Veggie::Celery => Colors::Green,
+            Veggie::Corn => Colors::Yellow,
+            Veggie::Squash => Colors::Beige,
+            Veggie::Sunflower => Colors::Brown,
+            Veggie::Carrot | Veggie::Pumpkin => Colors::Orange,
             Veggie::Garlic => Colors::Grey
         }
     }
 
     pub fn face_fg(&self) -> Colors {
         match self {
-            Veggie::Carrot | Veggie::Garlic => Colors::Black,
+            Veggie::Carrot | Veggie::Garlic | Veggie::Pumpkin | Veggie::Corn => Colors::Black,
+            Veggie::Squash => Colors::DarkRed,
             _ => Colors::Beige
         }
     }
@@ -77,13 +135,15 @@ fn update_faces(
 ) {
     for UpdateFaces(e, (e1, e2)) in events.iter() {
         if let Ok(c) = veg_children.get(*e) {
+            let c = c.iter().filter(|f| faces.contains(**f)).collect::<Vec<&Entity>>();
+
             if let Some(f1) = c.get(0) {
-                let mut sprite = faces.get_mut(*f1).unwrap();
+                let mut sprite = faces.get_mut(**f1).unwrap();
                 sprite.index = e1.index();
             }
 
             if let Some(f2) = c.get(1) {
-                let mut sprite = faces.get_mut(*f2).unwrap();
+                let mut sprite = faces.get_mut(**f2).unwrap();
                 sprite.index = e2.index();
             }
         }
@@ -93,6 +153,30 @@ fn update_faces(
 #[derive(Component)]
 pub struct Face;
 
+/// Lock marker of a veggie that can't be moved
+#[derive(Component)]
+pub struct Lock;
+
+pub fn add_lock(
+    commands: &mut Commands,
+    textures: &Res<Textures>,
+    veggie: Entity,
+) {
+    commands
+        .entity(veggie)
+        .with_children(|parent| {
+            parent
+                .spawn(text_mode_bundle(
+                    &Colors::Beige,
+                    &Colors::DarkRed,
+                    4,
+                    31., 1., 0.0000001,
+                    textures.faces.clone()
+                ))
+                .insert(Lock);
+        });
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Expression {
     Neutral,
@@ -126,7 +210,7 @@ pub fn spawn_veggie<'w, 's, 'a>(
                 anchor: Anchor::BottomLeft,
                 ..Default::default()
             },
-            texture_atlas: textures.fruit.clone(),
+            texture_atlas: if veggie.span() > 1 { textures.big_fruit.clone() } else { textures.fruit.clone() },
             transform: Transform {
                 translation: position,
                 ..Default::default()
@@ -134,6 +218,7 @@ pub fn spawn_veggie<'w, 's, 'a>(
             ..Default::default()
         })
         .insert(*veggie)
+        .insert(Clickable::square(40. * veggie.span() as f32))
         .with_children(|parent| {
             veggie.faces().iter().for_each(|(x, y)| {
                 parent